@@ -0,0 +1,154 @@
+use crate::{vec3::Vec3, ray::Ray};
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    /// Smallest corner of the box
+    pub min: Vec3,
+    /// Largest corner of the box
+    pub max: Vec3
+}
+
+impl Aabb {
+
+    /// Create a new bounding box from two corners
+    /// # Arguments
+    /// * 'min' - Smallest corner
+    /// * 'max' - Largest corner
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        return Aabb { min, max };
+    }
+
+    /// Create an empty bounding box
+    /// # Returns
+    /// * Inverted box which any point or box can be grown into
+    pub fn empty() -> Aabb {
+        return Aabb {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)
+        };
+    }
+
+    /// Create the smallest bounding box containing every point
+    /// # Arguments
+    /// * 'points' - Points to enclose
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        let mut b = Aabb::empty();
+        for p in points.iter() {
+            b.grow(*p);
+        }
+        return b;
+    }
+
+    /// Grow the box so it contains a point
+    /// # Arguments
+    /// * 'p' - Point to enclose
+    pub fn grow(&mut self, p: Vec3) {
+        self.min = self.min.min(p);
+        self.max = self.max.max(p);
+    }
+
+    /// Calculate the box containing both this box and another
+    /// # Arguments
+    /// * 'other' - Second box
+    pub fn union(&self, other: Aabb) -> Aabb {
+        return Aabb::new(self.min.min(other.min), self.max.max(other.max));
+    }
+
+    /// Check if the box has been grown at least once
+    pub fn is_empty(&self) -> bool {
+        return self.min.x > self.max.x;
+    }
+
+    /// Center point of the box
+    pub fn centroid(&self) -> Vec3 {
+        return (self.min + self.max) * 0.5;
+    }
+
+    /// Surface area of the box, used by the SAH when building a BVH
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        return 2.0 * (d.x * d.y + d.y * d.z + d.z * d.x);
+    }
+
+    /// Check if a ray passes through the box using the slab method
+    /// # Arguments
+    /// * 'r' - The incoming ray
+    /// * 'inv_dir' - 1.0 / r.direction, precomputed once per ray
+    /// # Returns
+    /// * Distance along the ray to where it enters the box, None if it misses the box within the ray's interval
+    pub fn hit(&self, r: Ray, inv_dir: Vec3) -> Option<f64> {
+        let mut t_min = r.t_min;
+        let mut t_max = r.t_max;
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - r.origin[axis]) * inv_dir[axis];
+            let t1 = (self.max[axis] - r.origin[axis]) * inv_dir[axis];
+
+            // Swap if the ray travels in the negative direction on this axis
+            let (t0, t1) = if inv_dir[axis] < 0.0 { (t1, t0) } else { (t0, t1) };
+
+            // NaN from 0 * inf will fail these comparisons and leave the interval alone
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return None;
+            }
+        }
+        return Some(t_min);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        return Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    fn slab_hit(b: Aabb, origin: Vec3, direction: Vec3) -> Option<f64> {
+        let r = Ray::new(origin, direction);
+        let inv_dir = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        return b.hit(r, inv_dir);
+    }
+
+    #[test]
+    fn axis_parallel_ray_inside_slabs_hits() {
+        let t = slab_hit(unit_box(), Vec3::new(0.5, -0.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(t, Some(4.0));
+
+        // Negative zero flips the infinite inverse, which must not matter
+        let t = slab_hit(unit_box(), Vec3::new(0.5, -0.5, 5.0), Vec3::new(-0.0, 0.0, -1.0));
+        assert_eq!(t, Some(4.0));
+    }
+
+    #[test]
+    fn axis_parallel_ray_outside_slabs_misses() {
+        assert_eq!(slab_hit(unit_box(), Vec3::new(1.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)), None);
+        assert_eq!(slab_hit(unit_box(), Vec3::new(0.0, -1.5, -5.0), Vec3::new(0.0, 0.0, 1.0)), None);
+        assert_eq!(slab_hit(unit_box(), Vec3::new(-1.5, 0.0, 5.0), Vec3::new(0.0, -0.0, -1.0)), None);
+    }
+
+    #[test]
+    fn axis_parallel_ray_on_a_face_hits() {
+        // 0 * inf is NaN on the x axis here, the box is still entered through z
+        let t = slab_hit(unit_box(), Vec3::new(1.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(t, Some(4.0));
+        let t = slab_hit(unit_box(), Vec3::new(-1.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(t, Some(4.0));
+    }
+
+    #[test]
+    fn box_behind_or_past_the_ray_misses() {
+        assert_eq!(slab_hit(unit_box(), Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0)), None);
+        let r = Ray::new_bounded(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0001, 3.0);
+        assert_eq!(unit_box().hit(r, Vec3::new(f64::INFINITY, f64::INFINITY, 1.0)), None);
+    }
+}
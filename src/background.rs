@@ -0,0 +1,234 @@
+use std::{f64::consts::PI, fs::File, io::BufReader, sync::Arc};
+use image::codecs::hdr::HdrDecoder;
+use crate::{vec3::{Vec3, unit_vector}, random::random_double};
+
+/// What a ray sees when it escapes the world
+#[derive(Clone, Debug)]
+pub enum Background {
+    /// Same color in every direction
+    Solid(Vec3),
+    /// Blend from the bottom color straight down to the top color straight up
+    Gradient { bottom: Vec3, top: Vec3 },
+    /// Equirectangular image wrapped around the whole world
+    Environment(Arc<EnvironmentMap>)
+}
+
+impl Background {
+
+    /// The white to blue sky used when a scene doesn't pick one
+    pub fn sky() -> Background {
+        return Background::Gradient { bottom: Vec3::new(1.0, 1.0, 1.0), top: Vec3::new(0.5, 0.7, 1.0) };
+    }
+
+    /// Calculate the color seen in a direction
+    /// # Arguments
+    /// * 'direction' - Direction of the escaping ray, any length
+    pub fn value(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Background::Solid(color) => return *color,
+            Background::Gradient { bottom, top } => {
+                // Normalized since camera and bounced rays come in any length
+                let n = unit_vector(direction);
+                let t = (n.y + 1.0) * 0.5;
+
+                // Typical interpolation
+                return (*bottom * (1.0 - t)) + *top * t;
+            },
+            Background::Environment(map) => return map.value(unit_vector(direction))
+        }
+    }
+
+    /// The environment map, if the background is one
+    /// Only environment maps are worth sampling directly, the other backgrounds light everything evenly enough
+    pub fn environment(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Background::Environment(map) => return Some(map),
+            _ => return None
+        }
+    }
+}
+
+/// Equirectangular HDR image lighting the world from every direction
+/// The top row is straight up, the left edge is the -x direction
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// Linear colors, row by row from the top, already multiplied by the intensity
+    pixels: Vec<Vec3>,
+    /// Sine and cosine of the rotation around the y axis
+    sin_rotation: f64,
+    cos_rotation: f64,
+    /// Running total of every row's weight, used to pick a row
+    marginal_cdf: Vec<f64>,
+    /// Running total of the weights within each row, used to pick a pixel once the row is known
+    conditional_cdf: Vec<f64>
+}
+
+impl EnvironmentMap {
+
+    /// Load an environment map from an equirectangular image
+    /// # Arguments
+    /// * 'path' - Radiance .hdr image
+    /// * 'rotation' - Rotation around the y axis in degrees
+    /// * 'intensity' - Brightness every pixel is multiplied by
+    /// # Returns
+    /// * The environment map, or a message describing why the image couldn't be loaded
+    pub fn load(path: &str, rotation: f64, intensity: f64) -> Result<EnvironmentMap, String> {
+        let error = |e: String| format!("failed to load environment map {}: {}", path, e);
+        let file = File::open(path).map_err(|e| error(e.to_string()))?;
+
+        // Go through the HDR decoder itself, loading it as a regular image clips everything down to 8 bits
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(|e| error(e.to_string()))?;
+        let (width, height) = (decoder.metadata().width as usize, decoder.metadata().height as usize);
+        if width == 0 || height == 0 {
+            return Err(error("image is empty".to_string()));
+        }
+        let pixels = decoder.read_image_hdr().map_err(|e| error(e.to_string()))?.iter()
+            .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64) * intensity)
+            .collect();
+        return Ok(EnvironmentMap::new(width, height, pixels, rotation));
+    }
+
+    /// Create an environment map from pixels
+    /// # Arguments
+    /// * 'width, height' - Image size in pixels
+    /// * 'pixels' - Linear colors, row by row from the top
+    /// * 'rotation' - Rotation around the y axis in degrees
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>, rotation: f64) -> EnvironmentMap {
+        let mut marginal_cdf = Vec::with_capacity(height);
+        let mut conditional_cdf = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+
+        for y in 0..height {
+
+            // Rows near the poles get squashed into a smaller part of the sphere, so they count for less
+            let sin_theta = ((y as f64 + 0.5) / height as f64 * PI).sin();
+            let mut row = 0.0;
+            for x in 0..width {
+                row += pixels[y * width + x].luminance().max(0.0) * sin_theta;
+                conditional_cdf.push(row);
+            }
+            total += row;
+            marginal_cdf.push(total);
+        }
+
+        let rotation = rotation.to_radians();
+        return EnvironmentMap {
+            width,
+            height,
+            pixels,
+            sin_rotation: rotation.sin(),
+            cos_rotation: rotation.cos(),
+            marginal_cdf,
+            conditional_cdf
+        };
+    }
+
+    /// Find the pixel a direction points at
+    /// # Arguments
+    /// * 'direction' - Unit vector
+    /// # Returns
+    /// * Column and row of the pixel, and the sine of the angle from straight up
+    fn pixel(&self, direction: Vec3) -> (usize, usize, f64) {
+
+        // Undo the map's rotation
+        let x = direction.x * self.cos_rotation - direction.z * self.sin_rotation;
+        let z = direction.x * self.sin_rotation + direction.z * self.cos_rotation;
+
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = z.atan2(x);
+        let u = (phi + PI) / (2.0 * PI);
+        let v = theta / PI;
+
+        let column = ((u * self.width as f64) as usize).min(self.width - 1);
+        let row = ((v * self.height as f64) as usize).min(self.height - 1);
+        return (column, row, theta.sin());
+    }
+
+    /// Color seen in a direction
+    /// # Arguments
+    /// * 'direction' - Unit vector
+    pub fn value(&self, direction: Vec3) -> Vec3 {
+        let (column, row, _) = self.pixel(direction);
+        return self.pixels[row * self.width + column];
+    }
+
+    /// Total weight of every pixel, 0.0 for a black map
+    fn total(&self) -> f64 {
+        return self.marginal_cdf.last().copied().unwrap_or(0.0);
+    }
+
+    /// Weight of a single pixel
+    fn weight(&self, column: usize, row: usize) -> f64 {
+        let i = row * self.width + column;
+        let before = if column == 0 { 0.0 } else { self.conditional_cdf[i - 1] };
+        return self.conditional_cdf[i] - before;
+    }
+
+    /// Pick a random direction, brighter parts of the map are picked more often
+    /// # Returns
+    /// * Unit vector, the color seen along it and the probability density per unit solid angle
+    /// * None if the map is black everywhere
+    pub fn sample(&self) -> Option<(Vec3, Vec3, f64)> {
+        let total = self.total();
+        if total <= 0.0 {
+            return None;
+        }
+
+        // Pick a row, then a pixel within the row
+        let target = random_double() * total;
+        let row = self.marginal_cdf.partition_point(|&c| c <= target).min(self.height - 1);
+        let row_cdf = &self.conditional_cdf[row * self.width..(row + 1) * self.width];
+        let target = random_double() * row_cdf[self.width - 1];
+        let column = row_cdf.partition_point(|&c| c <= target).min(self.width - 1);
+
+        // Then a random spot within the pixel
+        let u = (column as f64 + random_double()) / self.width as f64;
+        let v = (row as f64 + random_double()) / self.height as f64;
+        let phi = u * 2.0 * PI - PI;
+        let theta = v * PI;
+        let sin_theta = theta.sin();
+        if sin_theta <= 0.0 {
+            return None;
+        }
+
+        let x = sin_theta * phi.cos();
+        let z = sin_theta * phi.sin();
+
+        // Apply the map's rotation
+        let direction = Vec3::new(
+            x * self.cos_rotation + z * self.sin_rotation,
+            theta.cos(),
+            -x * self.sin_rotation + z * self.cos_rotation
+        );
+
+        let pdf = self.pdf_pixel(column, row, sin_theta);
+        return Some((direction, self.pixels[row * self.width + column], pdf));
+    }
+
+    /// Probability density of sample picking a direction
+    /// # Arguments
+    /// * 'direction' - Unit vector
+    /// # Returns
+    /// * Density per unit solid angle
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let (column, row, sin_theta) = self.pixel(direction);
+        return self.pdf_pixel(column, row, sin_theta);
+    }
+
+    /// Probability density of sample picking a direction within a pixel
+    /// # Arguments
+    /// * 'column, row' - The pixel
+    /// * 'sin_theta' - Sine of the direction's angle from straight up
+    fn pdf_pixel(&self, column: usize, row: usize, sin_theta: f64) -> f64 {
+        let total = self.total();
+        if total <= 0.0 || sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // Density over the image, then converted to per unit solid angle on the sphere
+        let pdf_uv = self.weight(column, row) / total * (self.width * self.height) as f64;
+        return pdf_uv / (2.0 * PI * PI * sin_theta);
+    }
+}
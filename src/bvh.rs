@@ -0,0 +1,308 @@
+use crate::{aabb::Aabb, ray::Ray, hit::Hit, vec3::Vec3};
+
+/// Number of buckets primitives get sorted into when searching for the best split
+const SAH_BINS: usize = 12;
+
+/// Leaves never get split below this many primitives
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Leaves never hold more than this many primitives, even if the SAH says splitting isn't worth it
+const MAX_SAH_LEAF_SIZE: usize = 16;
+
+/// Cost of stepping into a node relative to intersecting one primitive
+const TRAVERSAL_COST: f64 = 1.0;
+
+/// Single node of a flattened BVH
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+    /// Bounds of everything below this node
+    bounds: Aabb,
+    /// Leaf: index of the first primitive in `Bvh::indices`
+    /// Interior: index of the second child, the first child always directly follows its parent
+    offset: usize,
+    /// Number of primitives in a leaf, 0 for interior nodes
+    count: usize
+}
+
+/// Bounding volume hierarchy built with the surface area heuristic
+/// The tree is stored depth first in a single array so traversal never chases pointers
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    /// Flattened nodes, the root is at index 0
+    nodes: Vec<BvhNode>,
+    /// Primitive indices, ordered so every leaf covers a contiguous range
+    indices: Vec<usize>
+}
+
+impl Bvh {
+
+    /// Create a BVH containing nothing
+    pub fn new_empty() -> Bvh {
+        return Bvh { nodes: Vec::new(), indices: Vec::new() };
+    }
+
+    /// Build a BVH over a set of primitives
+    /// # Arguments
+    /// * 'bounds' - Bounding box of every primitive, the index into this slice is what traversal hands back
+    /// # Returns
+    /// * A BVH ready for traversal
+    pub fn new(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(bounds.len() * 2),
+            indices: (0..bounds.len()).collect()
+        };
+        if bounds.is_empty() {
+            return bvh;
+        }
+
+        // Centroids decide which side of a split a primitive ends up on
+        let centroids: Vec<Vec3> = bounds.iter().map(|b| b.centroid()).collect();
+        bvh.build(bounds, &centroids, 0, bounds.len());
+        return bvh;
+    }
+
+    /// Check if the BVH contains no primitives
+    pub fn is_empty(&self) -> bool {
+        return self.nodes.is_empty();
+    }
+
+    /// Recursively build the node covering indices[start..end]
+    /// # Returns
+    /// * Index of the new node
+    fn build(&mut self, bounds: &[Aabb], centroids: &[Vec3], start: usize, end: usize) -> usize {
+        let node_index = self.nodes.len();
+
+        // Bounds of every primitive and of their centroids
+        let mut node_bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &i in self.indices[start..end].iter() {
+            node_bounds = node_bounds.union(bounds[i]);
+            centroid_bounds.grow(centroids[i]);
+        }
+        self.nodes.push(BvhNode { bounds: node_bounds, offset: start, count: end - start });
+
+        let count = end - start;
+        if count <= MAX_LEAF_SIZE {
+            return node_index;
+        }
+
+        // Split along the axis where the centroids are the most spread out
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z { 0 } else if extent.y > extent.z { 1 } else { 2 };
+        let axis_min = centroid_bounds.min[axis];
+        let axis_extent = extent[axis];
+
+        // Every centroid sits on the same spot, no split can separate them
+        if axis_extent <= 0.0 {
+            return node_index;
+        }
+
+        // Sort primitives into buckets along the axis
+        let bin_of = |c: Vec3| -> usize {
+            let b = ((c[axis] - axis_min) / axis_extent * SAH_BINS as f64) as usize;
+            return b.min(SAH_BINS - 1);
+        };
+        let mut bin_bounds = [Aabb::empty(); SAH_BINS];
+        let mut bin_counts = [0usize; SAH_BINS];
+        for &i in self.indices[start..end].iter() {
+            let b = bin_of(centroids[i]);
+            bin_bounds[b] = bin_bounds[b].union(bounds[i]);
+            bin_counts[b] += 1;
+        }
+
+        // Sweep from the right so the area of everything past each split is known
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let mut acc = Aabb::empty();
+        let mut acc_count = 0;
+        for b in (1..SAH_BINS).rev() {
+            acc = acc.union(bin_bounds[b]);
+            acc_count += bin_counts[b];
+            right_area[b] = acc.surface_area();
+            right_count[b] = acc_count;
+        }
+
+        // Sweep from the left and pick the cheapest split, split b puts bins [0, b) on the left
+        let mut best_split = 0;
+        let mut best_cost = f64::INFINITY;
+        let mut acc = Aabb::empty();
+        let mut acc_count = 0;
+        for b in 1..SAH_BINS {
+            acc = acc.union(bin_bounds[b - 1]);
+            acc_count += bin_counts[b - 1];
+            let cost = acc.surface_area() * acc_count as f64 + right_area[b] * right_count[b] as f64;
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = b;
+            }
+        }
+
+        // Compare against just intersecting everything in a leaf
+        let leaf_cost = count as f64;
+        let split_cost = TRAVERSAL_COST + best_cost / node_bounds.surface_area();
+        if split_cost >= leaf_cost && count <= MAX_SAH_LEAF_SIZE {
+            return node_index;
+        }
+
+        // Partition indices so the left side comes first
+        let mut mid = start;
+        for i in start..end {
+            if bin_of(centroids[self.indices[i]]) < best_split {
+                self.indices.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        // Should never happen since every bin boundary lies between centroids, but don't recurse forever
+        if mid == start || mid == end {
+            mid = start + count / 2;
+        }
+
+        // Build the children, the left child lands at node_index + 1
+        self.build(bounds, centroids, start, mid);
+        let right = self.build(bounds, centroids, mid, end);
+        self.nodes[node_index].offset = right;
+        self.nodes[node_index].count = 0;
+        return node_index;
+    }
+
+    /// Find the closest hit among the primitives a ray may intersect
+    /// Nodes are visited front to back and anything beyond the closest hit so far is skipped
+    /// # Arguments
+    /// * 'r' - The incoming ray
+    /// * 'hit_primitive' - Intersects the primitive with the given index, the ray handed over has its t_max
+    ///   shrunk to the closest hit so far so only closer hits should be returned
+    /// # Returns
+    /// * The closest hit along the ray, same as testing every primitive one after another
+    pub fn hit<F: Fn(usize, Ray) -> Hit>(&self, r: Ray, hit_primitive: F) -> Hit {
+        let mut closest_hit = Hit::new();
+        if self.is_empty() {
+            return closest_hit;
+        }
+
+        let mut r = r;
+        let inv_dir = Vec3::new(1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z);
+
+        // Nodes still waiting to be visited
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            // Checked again on the way out since a closer hit may have been found after the node was pushed
+            if node.bounds.hit(r, inv_dir).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                // Leaf, check each primitive it holds
+                for &i in self.indices[node.offset..node.offset + node.count].iter() {
+                    let hit = hit_primitive(i, r);
+                    if hit.t > 0.0 {
+                        r.t_max = hit.t;
+                        closest_hit = hit;
+                    }
+                }
+            } else {
+                let left = node_index + 1;
+                let right = node.offset;
+                let t_left = self.nodes[left].bounds.hit(r, inv_dir);
+                let t_right = self.nodes[right].bounds.hit(r, inv_dir);
+
+                // Push the nearer child last so it gets popped first
+                match (t_left, t_right) {
+                    (Some(tl), Some(tr)) => {
+                        if tl < tr {
+                            stack.push(right);
+                            stack.push(left);
+                        } else {
+                            stack.push(left);
+                            stack.push(right);
+                        }
+                    },
+                    (Some(_), None) => stack.push(left),
+                    (None, Some(_)) => stack.push(right),
+                    (None, None) => {}
+                }
+            }
+        }
+        return closest_hit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{triangle::Triangle, random, vec3::{random_vec_range, unit_vector}};
+
+    /// Random triangle soup, small enough that many leaves overlap
+    fn soup(count: usize) -> (Vec<Vec3>, Vec<Triangle>) {
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for i in 0..count {
+            let center = random_vec_range(-10.0, 10.0);
+            for _ in 0..3 {
+                positions.push(center + random_vec_range(-1.0, 1.0));
+            }
+            let first = (i * 3) as u32;
+            triangles.push(Triangle::new([first, first + 1, first + 2]));
+        }
+        return (positions, triangles);
+    }
+
+    /// Closest hit found by testing every triangle one after another
+    fn brute_force(positions: &[Vec3], triangles: &[Triangle], r: Ray) -> Hit {
+        let mut r = r;
+        let mut closest_hit = Hit::new();
+        for (i, trig) in triangles.iter().enumerate() {
+            let mut hit = trig.hit(positions, r);
+            if hit.t > 0.0 {
+                hit.triangle = i;
+                r.t_max = hit.t;
+                closest_hit = hit;
+            }
+        }
+        return closest_hit;
+    }
+
+    #[test]
+    fn traversal_matches_brute_force() {
+        random::seed(7);
+        let (positions, triangles) = soup(500);
+        let bounds: Vec<Aabb> = triangles.iter().map(|t| t.bounds(&positions)).collect();
+        let bvh = Bvh::new(&bounds);
+
+        let mut hits = 0;
+        for i in 0..2000 {
+            let origin = random_vec_range(-15.0, 15.0);
+            let direction = unit_vector(random_vec_range(-1.0, 1.0));
+
+            // Every other ray stops partway so t_max has to be respected too
+            let t_max = if i % 2 == 0 { f64::INFINITY } else { random::random_range(1.0, 10.0) };
+            let r = Ray::new_bounded(origin, direction, 0.0001, t_max);
+
+            let expected = brute_force(&positions, &triangles, r);
+            let found = bvh.hit(r, |i, r| {
+                let mut hit = triangles[i].hit(&positions, r);
+                hit.triangle = i;
+                return hit;
+            });
+            assert_eq!(found.t, expected.t, "ray {i}");
+            if expected.t > 0.0 {
+                assert_eq!(found.triangle, expected.triangle, "ray {i}");
+                hits += 1;
+            }
+        }
+
+        // Make sure the rays weren't all misses
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn empty_bvh_hits_nothing() {
+        let bvh = Bvh::new(&[]);
+        let hit = bvh.hit(Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)), |_, _| panic!("no primitives to test"));
+        assert!(hit.t < 0.0);
+    }
+}
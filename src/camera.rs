@@ -0,0 +1,66 @@
+use crate::{vec3::{Vec3, cross, unit_vector, random_in_unit_disk}, ray::Ray};
+
+/// Camera struct
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    /// Where every ray starts
+    pub origin: Vec3,
+    /// Bottom left corner of the viewport
+    pub lower_left_corner: Vec3,
+    /// Vector across the full width of the viewport
+    pub horizontal: Vec3,
+    /// Vector up the full height of the viewport
+    pub vertical: Vec3,
+    /// Camera's right and up directions, the lens is sampled in their plane
+    pub u: Vec3,
+    pub v: Vec3,
+    /// Radius of the lens rays are sampled from, 0.0 is a pinhole with everything in focus
+    pub lens_radius: f64
+}
+
+impl Camera {
+
+    /// Create a new camera
+    /// # Arguments
+    /// * 'look_from' - Position of the camera
+    /// * 'look_at' - Point the camera is looking at
+    /// * 'vup' - Which way is up, used to keep the camera level
+    /// * 'vfov' - Vertical field of view in degrees NOT radians
+    /// * 'aspect_ratio' - Image width divided by image height
+    /// * 'aperture' - Diameter of the lens, larger blurs more
+    /// * 'focus_dist' - Distance from the camera to the plane that is perfectly in focus
+    pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64) -> Camera {
+
+        // Viewport properties, sized for a viewport 1 unit in front of the camera
+        let h = (vfov.to_radians() / 2.0).tan();
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        // Orthonormal basis, w points backwards out of the camera
+        let w = unit_vector(look_from - look_at);
+        let u = unit_vector(cross(vup, w));
+        let v = cross(w, u);
+
+        // The viewport is pushed out to the focus plane, rays from anywhere on the lens meet there
+        let origin = look_from;
+        let horizontal = u * viewport_width * focus_dist;
+        let vertical = v * viewport_height * focus_dist;
+        let lower_left_corner = origin - (horizontal/2.0) - (vertical/2.0) - w * focus_dist;
+
+        return Camera { origin, lower_left_corner, horizontal, vertical, u, v, lens_radius: aperture / 2.0 };
+    }
+
+    /// Calculate the ray through a point on the viewport
+    /// # Arguments
+    /// * 's, t' - Position on the viewport in the range 0-1, (0,0) is the bottom left
+    /// # Returns
+    /// * Ray starting at a random point on the lens going through the viewport
+    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+
+        // Pick where on the lens the ray leaves from
+        let rd = random_in_unit_disk() * self.lens_radius;
+        let offset = self.u * rd.x + self.v * rd.y;
+
+        return Ray::new(self.origin + offset, self.lower_left_corner + (self.horizontal*s) + (self.vertical*t) - self.origin - offset);
+    }
+}
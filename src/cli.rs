@@ -0,0 +1,130 @@
+use crate::render::{DrawingMode, RenderSettings};
+
+/// Help text printed for --help
+pub const USAGE: &str = "Usage: rust_raytracer [OPTIONS] [SCENE]
+
+Renders SCENE, a TOML scene file (default: scenes/default.toml)
+
+Options:
+  -o, --output <PATH>          Image to write, .png, .ppm, .exr or .hdr (default: output.ppm)
+  -r, --resolution <WxH>       Image size in pixels, e.g. 1920x1080
+  -w, --width <PIXELS>         Image width, the height keeps the scene's aspect ratio
+  -s, --samples <N>            Samples per pixel
+  -d, --max-depth <N>          Number of bounces a ray can perform
+  -m, --mode <MODE>            Drawing mode: colors, normals or samples
+      --seed <N>               Random seed, fix it to get the same image every run
+  -h, --help                   Print this help";
+
+/// Command line arguments
+/// Everything besides the scene and output is optional and overrides the scene file
+#[derive(Debug)]
+pub struct Args {
+    /// Scene file to render
+    pub scene: String,
+    /// Image file to write
+    pub output: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub mode: Option<DrawingMode>,
+    pub seed: Option<u64>,
+    /// Print the usage instead of rendering
+    pub help: bool
+}
+
+impl Args {
+
+    /// Parse command line arguments
+    /// # Arguments
+    /// * 'args' - Arguments without the program name
+    /// # Returns
+    /// * The parsed arguments, or a message describing what's wrong with them
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut parsed = Args {
+            scene: "scenes/default.toml".to_string(),
+            output: "output.ppm".to_string(),
+            width: None,
+            height: None,
+            samples: None,
+            max_depth: None,
+            mode: None,
+            seed: None,
+            help: false
+        };
+        let mut scene: Option<String> = None;
+
+        let mut args = args;
+        while let Some(arg) = args.next() {
+
+            // Allow both "--samples 10" and "--samples=10"
+            let (flag, inline) = match arg.split_once('=') {
+                Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+                _ => (arg.clone(), None)
+            };
+            let mut value = || -> Result<String, String> {
+                return inline.clone().or_else(|| args.next()).ok_or(format!("{} needs a value", flag));
+            };
+
+            match flag.as_str() {
+                "-o" | "--output" => parsed.output = value()?,
+                "-r" | "--resolution" => {
+                    let v = value()?;
+                    let (w, h) = v.split_once('x').ok_or(format!("resolution '{}' should look like 1920x1080", v))?;
+                    parsed.width = Some(parse_number(w, "width")?);
+                    parsed.height = Some(parse_number(h, "height")?);
+                },
+                "-w" | "--width" => parsed.width = Some(parse_number(&value()?, "width")?),
+                "-s" | "--samples" => parsed.samples = Some(parse_number(&value()?, "samples")?),
+                "-d" | "--max-depth" => parsed.max_depth = Some(parse_number(&value()?, "max depth")?),
+                "-m" | "--mode" => parsed.mode = Some(value()?.parse()?),
+                "--seed" => parsed.seed = Some(parse_number(&value()?, "seed")?),
+                "-h" | "--help" => parsed.help = true,
+                _ if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+                _ => {
+                    if scene.is_some() {
+                        return Err(format!("unexpected argument {}", arg));
+                    }
+                    scene = Some(arg);
+                }
+            }
+        }
+
+        if let Some(scene) = scene {
+            parsed.scene = scene;
+        }
+        return Ok(parsed);
+    }
+
+    /// Override a scene's render settings with whatever was given on the command line
+    /// # Arguments
+    /// * 'settings' - Settings from the scene file
+    /// # Returns
+    /// * The settings to render with
+    pub fn apply(&self, settings: RenderSettings) -> Result<RenderSettings, String> {
+        let mut s = settings;
+
+        // A width on its own keeps the scene's aspect ratio
+        if let Some(width) = self.width {
+            s.height = self.height.unwrap_or((width as f64 * settings.height as f64 / settings.width as f64).round() as u32);
+            s.width = width;
+        }
+        if s.width < 2 || s.height < 2 {
+            return Err("image must be at least 2x2 pixels".to_string());
+        }
+
+        s.samples = self.samples.unwrap_or(s.samples);
+        s.max_depth = self.max_depth.unwrap_or(s.max_depth);
+        s.mode = self.mode.unwrap_or(s.mode);
+        s.seed = self.seed.unwrap_or(s.seed);
+        return Ok(s);
+    }
+}
+
+/// Parse a number given to an option
+/// # Arguments
+/// * 'v' - Text to parse
+/// * 'name' - What the number is, used in the error message
+fn parse_number<T: std::str::FromStr>(v: &str, name: &str) -> Result<T, String> {
+    return v.parse().map_err(|_| format!("{} '{}' is not a valid number", name, v));
+}
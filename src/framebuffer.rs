@@ -0,0 +1,28 @@
+use crate::vec3::Vec3;
+
+/// Floating point image holding the linear light of every pixel
+/// Nothing is clamped or gamma corrected so the full range of the render is kept
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    /// Colors of every pixel, row by row from the top left
+    pub pixels: Vec<Vec3>
+}
+
+impl Framebuffer {
+
+    /// Create a new black framebuffer
+    /// # Arguments
+    /// * 'width, height' - Size in pixels
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        return Framebuffer { width, height, pixels: vec![Vec3::new(0.0, 0.0, 0.0); (width * height) as usize] };
+    }
+
+    /// Get the color of a single pixel
+    /// # Arguments
+    /// * 'x, y' - Pixel position, (0,0) is the top left
+    pub fn get(&self, x: u32, y: u32) -> Vec3 {
+        return self.pixels[(y * self.width + x) as usize];
+    }
+}
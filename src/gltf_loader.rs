@@ -0,0 +1,408 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use gltf::{Gltf, Node, Document, buffer, image::Source, texture::WrappingMode, material::AlphaMode, camera::Projection,
+    khr_lights_punctual::Kind, mesh::Mode};
+use crate::{vec3::{Vec3, cross, unit_vector}, mesh::{Mesh, MeshBuilder, Vertex}, world::World, transform::Transform, instance::Instance,
+    material::{MaterialEnum, Principled, Dielectric, Emissive}, texture::{Texture, ImageTexture, NormalMap, WrapMode},
+    light::{PunctualLight, PointLight, SpotLight, DirectionalLight}};
+
+/// Where a glTF camera is and which way it looks
+#[derive(Copy, Clone, Debug)]
+pub struct GltfCamera {
+    pub position: Vec3,
+    /// Unit vector the camera looks along
+    pub forward: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view in degrees
+    pub vfov: f64,
+    /// Image width divided by image height, if the file gives one
+    pub aspect_ratio: Option<f64>
+}
+
+/// Undo the percent encoding of a URI, files with spaces in their names are written with %20
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = uri.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+/// Read what a URI points at, either data embedded in it or a file next to the glTF file
+/// # Arguments
+/// * 'uri' - The URI as written in the file
+/// * 'base' - Directory relative paths start from
+fn read_uri(uri: &str, base: &Path) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or("only base64 data URIs are supported".to_string())?;
+        return base64::decode(encoded).map_err(|e| format!("invalid base64 data URI: {}", e));
+    }
+    let path = base.join(percent_decode(uri));
+    return fs::read(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e));
+}
+
+/// Sets of texture coordinates a material's textures use
+/// # Arguments
+/// * 'material' - The glTF material
+/// # Returns
+/// * Index of the TEXCOORD_n attribute each of its base color, metallic roughness and normal textures read
+fn tex_coord_sets(material: &gltf::Material) -> Vec<u32> {
+    let pbr = material.pbr_metallic_roughness();
+    return [
+        pbr.base_color_texture().map(|info| info.tex_coord()),
+        pbr.metallic_roughness_texture().map(|info| info.tex_coord()),
+        material.normal_texture().map(|info| info.tex_coord())
+    ].into_iter().flatten().collect();
+}
+
+/// Loads everything a glTF file refers to, keeping track of what's been loaded already
+struct Loader<'a> {
+    document: &'a Document,
+    /// Directory of the glTF file, external buffers and images are relative to it
+    base: &'a Path,
+    /// Contents of every buffer, in order
+    buffers: Vec<Vec<u8>>,
+    /// Decoded images, by image index and whether they hold sRGB colors
+    images: HashMap<(usize, bool), Arc<ImageTexture>>,
+    /// Every material in the file after the default one, shared by every mesh
+    materials: Vec<MaterialEnum>,
+    /// Meshes already loaded, by mesh index, nodes using the same mesh share it
+    meshes: HashMap<usize, Arc<Mesh>>,
+    /// First camera found, in the order nodes are visited
+    camera: Option<GltfCamera>
+}
+
+impl<'a> Loader<'a> {
+
+    /// Load a texture's image
+    /// # Arguments
+    /// * 'texture' - The texture, its sampler picks the wrap mode
+    /// * 'srgb' - Whether the image holds colors or data
+    fn image(&mut self, texture: &gltf::Texture, srgb: bool) -> Result<Arc<ImageTexture>, String> {
+        let index = texture.source().index();
+        if let Some(image) = self.images.get(&(index, srgb)) {
+            return Ok(image.clone());
+        }
+
+        // Both directions share one wrap mode here, the horizontal one
+        let sampler = texture.sampler();
+        if sampler.wrap_s() != sampler.wrap_t() {
+            eprintln!("Warning: texture {} wraps differently across and down, using {:?} for both", texture.index(), sampler.wrap_s());
+        }
+        let wrap = match sampler.wrap_s() {
+            WrappingMode::ClampToEdge => WrapMode::Clamp,
+            WrappingMode::MirroredRepeat => WrapMode::Mirror,
+            WrappingMode::Repeat => WrapMode::Repeat
+        };
+
+        let name = format!("image {}", index);
+        let image = match texture.source().source() {
+            Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                let bytes = buffer.get(view.offset()..view.offset() + view.length()).ok_or(format!("{} is outside its buffer", name))?;
+                ImageTexture::from_memory(bytes, &name, wrap, srgb)?
+            },
+            Source::Uri { uri, .. } => ImageTexture::from_memory(&read_uri(uri, self.base)?, &name, wrap, srgb)?
+        };
+        let image = Arc::new(image);
+        self.images.insert((index, srgb), image.clone());
+        return Ok(image);
+    }
+
+    /// Create one of our materials from a glTF material
+    /// # Arguments
+    /// * 'material' - The glTF material
+    fn material(&mut self, material: &gltf::Material) -> Result<MaterialEnum, String> {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor().map(|c| c as f64);
+        let factor = Vec3::new(r, g, b);
+
+        // Anything glowing is a light, its textures are ignored
+        let emissive = material.emissive_factor().map(|c| c as f64);
+        let emissive = Vec3::new(emissive[0], emissive[1], emissive[2]);
+        if !emissive.near_zero() {
+            return Ok(MaterialEnum::Emissive(Emissive::new(emissive, 1.0)));
+        }
+
+        // Blended see through materials are taken to be glass
+        if material.alpha_mode() == AlphaMode::Blend && a < 1.0 {
+            return Ok(MaterialEnum::Dielectric(Dielectric::new(factor, 1.5)));
+        }
+
+        let sets = tex_coord_sets(material);
+        if sets.iter().any(|&s| s != sets[0]) {
+            eprintln!("Warning: textures of material {} use different texture coordinates, all of them use TEXCOORD_{}", material.index().unwrap_or(0), sets[0]);
+        }
+
+        // Factors multiply their textures, they're baked into copies of the images
+        let base_color = match pbr.base_color_texture() {
+            Some(info) => Texture::Image(Arc::new(self.image(&info.texture(), true)?.map_pixels(|p| p * factor))),
+            None => Texture::Solid(factor)
+        };
+
+        // Roughness is in the green channel and metallic in the blue one of the same image
+        let metallic = pbr.metallic_factor() as f64;
+        let roughness = pbr.roughness_factor() as f64;
+        let (metallic, roughness) = match pbr.metallic_roughness_texture() {
+            Some(info) => {
+                let image = self.image(&info.texture(), false)?;
+                (
+                    Texture::Image(Arc::new(image.map_pixels(|p| Vec3::new(p.z, p.z, p.z) * metallic))),
+                    Texture::Image(Arc::new(image.map_pixels(|p| Vec3::new(p.y, p.y, p.y) * roughness)))
+                )
+            },
+            None => (Texture::Solid(Vec3::new(metallic, metallic, metallic)), Texture::Solid(Vec3::new(roughness, roughness, roughness)))
+        };
+
+        let mut principled = Principled::new(base_color, metallic, roughness, 0.5);
+        if let Some(normal) = material.normal_texture() {
+            principled.normal_map = Some(NormalMap::Tangent { image: self.image(&normal.texture(), false)?, strength: normal.scale() as f64 });
+        }
+        return Ok(MaterialEnum::Principled(principled));
+    }
+
+    /// Create a mesh from a glTF mesh, in its own space
+    /// # Arguments
+    /// * 'mesh' - The glTF mesh
+    fn mesh(&self, mesh: &gltf::Mesh) -> Result<Mesh, String> {
+        let mut builder = MeshBuilder::new();
+        for (number, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() != Mode::Triangles {
+                eprintln!("Warning: skipping a primitive of mesh {} that isn't made of triangles", mesh.index());
+                continue;
+            }
+
+            let reader = primitive.reader(|b| self.buffers.get(b.index()).map(|d| d.as_slice()));
+            let points: Vec<Vec3> = reader.read_positions()
+                .ok_or(format!("mesh {} has a primitive without positions", mesh.index()))?
+                .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                .collect();
+            let normals: Option<Vec<Vec3>> = reader.read_normals()
+                .map(|n| n.map(|n| unit_vector(Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))).collect());
+
+            // Meshes only have one set of texture coordinates here, the one the material's first texture uses
+            // glTF's texture coordinates start at the top left of the image, ours at the bottom left
+            let set = tex_coord_sets(&primitive.material()).first().copied().unwrap_or(0);
+            let uvs: Option<Vec<Vec3>> = reader.read_tex_coords(set)
+                .map(|t| t.into_f32().map(|t| Vec3::new(t[0] as f64, 1.0 - t[1] as f64, 0.0)).collect());
+
+            // Without indices every 3 vertices make a triangle
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..points.len()).collect()
+            };
+            if indices.iter().any(|&i| i >= points.len()) {
+                return Err(format!("mesh {} has an index past the end of its vertices", mesh.index()));
+            }
+            if normals.as_ref().is_some_and(|n| n.len() != points.len()) {
+                return Err(format!("mesh {} has a different number of normals than positions", mesh.index()));
+            }
+            if uvs.as_ref().is_some_and(|t| t.len() != points.len()) {
+                return Err(format!("mesh {} has a different number of texture coordinates than positions", mesh.index()));
+            }
+
+            // Material 0 is the default, the file's own materials follow it
+            let material = primitive.material().index().map_or(0, |i| i + 1);
+            for corner in indices.chunks_exact(3) {
+                let corner = [corner[0], corner[1], corner[2]];
+                let p = corner.map(|i| points[i]);
+
+                // Degenerate triangles have no normal and can't be hit anyway
+                let face_normal = cross(p[1] - p[0], p[2] - p[0]);
+                if face_normal.near_zero() {
+                    continue;
+                }
+
+                // Vertices are only shared within a primitive
+                let corners = corner.map(|i| {
+                    let mut vertex = Vertex::new((number, i), points[i]);
+                    vertex.normal = normals.as_ref().map(|n| n[i]);
+                    if let Some(uvs) = &uvs {
+                        vertex.uv = uvs[i];
+                    }
+                    return vertex;
+                });
+                builder.add_triangle(corners, true, material);
+            }
+        }
+
+        // glTF's default material is white, fully metallic and fully rough
+        let default = Principled::new(Texture::Solid(Vec3::new(1.0, 1.0, 1.0)), Texture::Solid(Vec3::new(1.0, 1.0, 1.0)), Texture::Solid(Vec3::new(1.0, 1.0, 1.0)), 0.5);
+        let mut result = builder.build();
+        result.materials = std::iter::once(MaterialEnum::Principled(default)).chain(self.materials.iter().cloned()).collect();
+        return Ok(result);
+    }
+
+    /// Add a node and all of its children to the world
+    /// # Arguments
+    /// * 'node' - The node
+    /// * 'parent' - Transform of the node's parent, relative to the world
+    /// * 'world' - World to add meshes and lights to
+    fn node(&mut self, node: &Node, parent: &Transform, world: &mut World) -> Result<(), String> {
+        let local = Transform::from_columns(node.transform().matrix().map(|column| column.map(|v| v as f64)));
+        let transform = local.then(parent);
+
+        if let Some(mesh) = node.mesh() {
+            let shared = match self.meshes.get(&mesh.index()) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let shared = self.mesh(&mesh)?.into_shared();
+                    self.meshes.insert(mesh.index(), shared.clone());
+                    shared
+                }
+            };
+
+            // Nodes scaled down to nothing can't be seen anyway
+            if let (false, Ok(instance)) = (shared.triangles.is_empty(), Instance::new(shared, transform)) {
+                world.add_instance(instance);
+            }
+        }
+
+        // Lights and cameras point down their node's -z axis
+        let position = transform.point(Vec3::new(0.0, 0.0, 0.0));
+        let forward = unit_vector(transform.vector(Vec3::new(0.0, 0.0, -1.0)));
+
+        // Intensities are used as they are, candela for point and spot lights and lux for directional ones
+        if let Some(light) = node.light() {
+            let emission = Vec3::new(light.color()[0] as f64, light.color()[1] as f64, light.color()[2] as f64) * light.intensity() as f64;
+            world.add_light(match light.kind() {
+                Kind::Point => PunctualLight::Point(PointLight::new(position, emission)),
+                Kind::Spot { inner_cone_angle, outer_cone_angle } => {
+                    let spot = SpotLight::new(position, forward, emission, (inner_cone_angle as f64).to_degrees(), (outer_cone_angle as f64).to_degrees());
+                    PunctualLight::Spot(spot.map_err(|e| format!("node {}: {}", node.index(), e))?)
+                },
+                Kind::Directional => PunctualLight::Directional(DirectionalLight::new(forward, emission))
+            });
+        }
+
+        if let (None, Some(camera)) = (self.camera, node.camera()) {
+            match camera.projection() {
+                Projection::Perspective(perspective) => {
+                    self.camera = Some(GltfCamera {
+                        position,
+                        forward,
+                        up: unit_vector(transform.vector(Vec3::new(0.0, 1.0, 0.0))),
+                        vfov: (perspective.yfov() as f64).to_degrees(),
+                        aspect_ratio: perspective.aspect_ratio().map(|a| a as f64)
+                    });
+                },
+                Projection::Orthographic(_) => eprintln!("Warning: skipping orthographic camera {}", camera.index())
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, &transform, world)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Load a glTF 2.0 file, either .gltf with its buffers and images embedded or alongside it, or binary .glb
+/// Every mesh, material and KHR_lights_punctual light of the default scene is added to the world
+/// # Arguments
+/// * 'path' - Path of the glTF file
+/// * 'world' - World to add to
+/// # Returns
+/// * The first perspective camera in the scene if there is one, or a message describing why the file couldn't be loaded
+pub fn load_gltf(path: &Path, world: &mut World) -> Result<Option<GltfCamera>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read file: {}", e))?;
+    return parse_gltf(&bytes, path.parent().unwrap_or(Path::new("")), world);
+}
+
+/// Parse glTF 2.0 already read into memory, .gltf or binary .glb
+/// # Arguments
+/// * 'bytes' - Contents of the file
+/// * 'base' - Directory buffers and images that aren't embedded are read relative to
+/// * 'world' - World to add to
+/// # Returns
+/// * The first perspective camera in the scene if there is one, or a message describing why the file couldn't be parsed
+pub fn parse_gltf(bytes: &[u8], base: &Path, world: &mut World) -> Result<Option<GltfCamera>, String> {
+    let gltf = Gltf::from_slice(bytes).map_err(|e| e.to_string())?;
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.document.buffers() {
+        let data = match buffer.source() {
+            buffer::Source::Bin => gltf.blob.clone().ok_or("binary buffer used without a binary chunk".to_string())?,
+            buffer::Source::Uri(uri) => read_uri(uri, base)?
+        };
+        if data.len() < buffer.length() {
+            return Err(format!("buffer {} is shorter than it says it is", buffer.index()));
+        }
+        buffers.push(data);
+    }
+
+    let mut loader = Loader { document: &gltf.document, base, buffers, images: HashMap::new(), materials: Vec::new(), meshes: HashMap::new(), camera: None };
+    for material in gltf.document.materials() {
+        let material = loader.material(&material)?;
+        loader.materials.push(material);
+    }
+
+    let scene = loader.document.default_scene().or_else(|| loader.document.scenes().next()).ok_or("file has no scenes".to_string())?;
+    for node in scene.nodes() {
+        loader.node(&node, &Transform::identity(), world)?;
+    }
+    return Ok(loader.camera);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableEnum;
+
+    /// One triangle with two sets of texture coordinates, all 0 in the first and (0.25, 0.5) in the second
+    /// Its material's base color texture reads the second, through a sampler wrapping differently each way
+    const TWO_SETS: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 }, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0, "texCoord": 1 } } }],
+        "textures": [{ "source": 0, "sampler": 0 }],
+        "samplers": [{ "wrapS": 10497, "wrapT": 33071 }],
+        "images": [{ "uri": "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAIAAACQd1PeAAAADElEQVR4nGP4//8/AAX+Av4N70a4AAAAAElFTkSuQmCC" }],
+        "buffers": [{ "byteLength": 84, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAPgAAAD8AAIA+AAAAPwAAgD4AAAA/" }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 84 }],
+        "accessors": [
+            { "bufferView": 0, "byteOffset": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC2" },
+            { "bufferView": 0, "byteOffset": 60, "componentType": 5126, "count": 3, "type": "VEC2" }
+        ]
+    }"#;
+
+    #[test]
+    fn textures_read_the_texture_coordinates_they_name() {
+        let mut world = World::new();
+        parse_gltf(TWO_SETS.as_bytes(), Path::new(""), &mut world).unwrap();
+
+        let HittableEnum::Instance(instance) = &world.objects[0] else {
+            panic!("the mesh should have been added as an instance");
+        };
+
+        // v is flipped, glTF's images start at the top
+        for uv in instance.mesh.uvs.iter() {
+            assert_eq!((uv.x, uv.y), (0.25, 0.5));
+        }
+    }
+
+    #[test]
+    fn texture_coordinates_must_match_the_positions() {
+
+        // The second set only has 2 entries for 3 positions
+        let short = TWO_SETS.replace(r#""byteOffset": 60, "componentType": 5126, "count": 3"#, r#""byteOffset": 60, "componentType": 5126, "count": 2"#);
+        let mut world = World::new();
+        let loaded = parse_gltf(short.as_bytes(), Path::new(""), &mut world);
+        assert_eq!(loaded.err(), Some("mesh 0 has a different number of texture coordinates than positions".to_string()));
+    }
+}
@@ -1,31 +1,101 @@
-use crate::{triangle::Triangle, vec3::Vec3, material::{MaterialEnum, Diffuse}};
+use crate::{ray::Ray, vec3::{Vec3, unit_vector, dot, cross, orthonormal_basis}, material::{Material, MaterialEnum, Diffuse}, texture::Texture};
 
 /// Contains information about the triangle the ray hit
 #[derive(Clone, Debug)]
 pub struct Hit {
-    /// The hit triangle
-    pub triangle: Triangle,
+    /// Index of the hit triangle in its mesh
+    pub triangle: usize,
     /// The x,y,z coordinates of the position the ray hit in the triangle
     pub at: Vec3,
-    /// Used to check if the ray hit a triangle
+    /// Distance along the ray to the hit, -1.0 if the ray hit nothing
     pub t: f64,
     /// The triangle's material
-    pub material: MaterialEnum
+    pub material: MaterialEnum,
+    /// Shading normal at the hit, interpolated if smooth shaded and always facing against the ray
+    pub normal: Vec3,
+    /// Normal of the triangle as a whole, decides which side was hit
+    pub geometric_normal: Vec3,
+    /// Whether the ray hit the outside of the triangle, the side its normal points out of
+    pub front_face: bool,
+    /// Texture coordinates at the hit, u and v in x and y
+    pub uv: Vec3,
+    /// Vertex color at the hit
+    pub color: Vec3,
+    /// Unit vector along the surface in the direction u increases, perpendicular to the normal pointing out of the triangle
+    pub tangent: Vec3,
+    /// Unit vector along the surface in the direction v increases, completes the shading frame with the tangent and normal
+    pub bitangent: Vec3,
+    /// Change in position per unit of u and per unit of v, None if the texture coordinates are degenerate
+    pub uv_derivatives: Option<(Vec3, Vec3)>,
+    /// Whether the world also samples this surface as a light when it's emissive, only mesh triangles are
+    pub light_sampled: bool
 }
 
 impl Hit {
     /// Create a new empty hit object
     /// # Default Values
-    /// * 'triangle' - Empty triangle
-    /// * 'at' - Hit position (0,0,-500000000)
+    /// * 'triangle' - 0
+    /// * 'at' - Hit position (0,0,0)
     /// * 't' - -1.0
     /// * 'material' - White diffuse material
+    /// * 'normal' - (0,0,0) until set_face_normal is called
+    /// * 'geometric_normal' - (0,0,0)
+    /// * 'front_face' - True
+    /// * 'uv' - (0,0,0)
+    /// * 'color' - White
+    /// * 'tangent, bitangent' - (0,0,0) until set_face_normal is called
+    /// * 'uv_derivatives' - None
+    /// * 'light_sampled' - False
     pub fn new() -> Hit {
         return Hit {
-            triangle: Triangle::new_empty(),
-            at: Vec3::new(0.0, 0.0, -5000000000000.0),
+            triangle: 0,
+            at: Vec3::new(0.0, 0.0, 0.0),
             t: -1.0,
-            material: MaterialEnum::Diffuse(Diffuse::new(Vec3::new(1.0, 1.0, 1.0)))
+            material: MaterialEnum::Diffuse(Diffuse::new(Texture::Solid(Vec3::new(1.0, 1.0, 1.0)))),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(0.0, 0.0, 0.0),
+            front_face: true,
+            uv: Vec3::new(0.0, 0.0, 0.0),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
+            uv_derivatives: None,
+            light_sampled: false
         };
     }
-}
\ No newline at end of file
+
+    /// Calculate the shading normal, tangent frame and which side of the surface was hit
+    /// Applies the material's normal map, so the material, texture coordinates, geometric normal and uv derivatives must be set first
+    /// Only done once the closest hit is known, rather than for every triangle tested
+    /// # Arguments
+    /// * 'r' - The ray that hit the surface
+    /// * 'outward_normal' - Shading normal pointing out of the surface, interpolated if smooth shaded
+    /// * 'tangent' - Direction u increases along the surface, need not be perpendicular to the normal, (0,0,0) if there is none
+    pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3, tangent: Vec3) {
+        let mut outward_normal = outward_normal;
+
+        // Make the tangent perpendicular to the normal, any direction will do without texture coordinates
+        let tangent = tangent - outward_normal * dot(outward_normal, tangent);
+        self.tangent = if tangent.near_zero() { orthonormal_basis(outward_normal).0 } else { unit_vector(tangent) };
+
+        // Mirrored texture coordinates flip which way v runs
+        let handedness = match self.uv_derivatives {
+            Some((dpdu, dpdv)) if dot(cross(dpdu, dpdv), self.geometric_normal) < 0.0 => -1.0,
+            _ => 1.0
+        };
+        self.bitangent = cross(outward_normal, self.tangent) * handedness;
+
+        if let Some(map) = self.material.normal_map() {
+
+            // A mapped normal leaning away from the viewer would shade black, keep the unmapped one there
+            let mapped = map.apply(self, outward_normal);
+            if dot(mapped, r.direction) * dot(outward_normal, r.direction) > 0.0 {
+                outward_normal = mapped;
+            }
+        }
+
+        // The triangle's own normal decides the side, interpolated normals can lean past the ray
+        self.front_face = dot(r.direction, self.geometric_normal) < 0.0;
+        self.normal = if self.front_face { outward_normal } else { -outward_normal };
+    }
+}
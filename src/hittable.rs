@@ -0,0 +1,54 @@
+use crate::{ray::Ray, hit::Hit, aabb::Aabb, instance::Instance, shapes::{Sphere, Plane, Disk, AaBox, Cylinder}};
+
+/// Anything a ray can hit
+pub trait Hittable {
+    /// Check if the object has been hit by a ray
+    /// # Arguments
+    /// * 'r' - The incoming ray, only hits within its interval count
+    /// # Returns
+    /// * A hit struct containing the closest hit along the ray and its properties, t is -1.0 if nothing was hit
+    fn hit(&self, r: Ray) -> Hit;
+
+    /// Calculate the bounding box of the object
+    /// # Returns
+    /// * The box, None if the object goes on forever like a plane
+    fn bounds(&self) -> Option<Aabb>;
+}
+
+/// Every kind of object the world can hold
+#[derive(Clone, Debug)]
+pub enum HittableEnum {
+    Instance(Box<Instance>),
+    Sphere(Sphere),
+    Plane(Plane),
+    Disk(Disk),
+    AaBox(AaBox),
+    Cylinder(Cylinder)
+}
+
+impl Hittable for HittableEnum {
+
+    // Same as MaterialEnum, call the function of whichever object it actually is
+
+    fn hit(&self, r: Ray) -> Hit {
+        match self {
+            HittableEnum::Instance(object) => return object.hit(r),
+            HittableEnum::Sphere(object) => return object.hit(r),
+            HittableEnum::Plane(object) => return object.hit(r),
+            HittableEnum::Disk(object) => return object.hit(r),
+            HittableEnum::AaBox(object) => return object.hit(r),
+            HittableEnum::Cylinder(object) => return object.hit(r)
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        match self {
+            HittableEnum::Instance(object) => return object.bounds(),
+            HittableEnum::Sphere(object) => return object.bounds(),
+            HittableEnum::Plane(object) => return object.bounds(),
+            HittableEnum::Disk(object) => return object.bounds(),
+            HittableEnum::AaBox(object) => return object.bounds(),
+            HittableEnum::Cylinder(object) => return object.bounds()
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+use crate::{mesh::Mesh, transform::Transform, material::MaterialEnum, ray::Ray, hit::Hit, aabb::Aabb, vec3::Vec3, hittable::Hittable};
+
+/// A mesh placed in the world
+/// Many instances can share one mesh, each with its own transform and material
+#[derive(Clone, Debug)]
+pub struct Instance {
+    /// The shared mesh, see Mesh::into_shared
+    pub mesh: Arc<Mesh>,
+    /// Object to world and world to object transforms, None when the mesh is used where it is
+    transform: Option<(Transform, Transform)>,
+    /// Replaces every one of the mesh's materials on this instance
+    pub material: Option<MaterialEnum>
+}
+
+impl Instance {
+
+    /// Place a mesh in the world
+    /// # Arguments
+    /// * 'mesh' - The shared mesh
+    /// * 'transform' - Moves the mesh from its own space into the world
+    /// # Returns
+    /// * The instance using the mesh's own materials, or a message if the transform flattens it
+    pub fn new(mesh: Arc<Mesh>, transform: Transform) -> Result<Instance, String> {
+        if transform == Transform::identity() {
+            return Ok(Instance { mesh, transform: None, material: None });
+        }
+        let inverse = transform.inverse().ok_or("transform scales the mesh down to nothing".to_string())?;
+        return Ok(Instance { mesh, transform: Some((transform, inverse)), material: None });
+    }
+
+    /// Material of one of the mesh's triangles on this instance
+    /// # Arguments
+    /// * 'triangle' - Index of the triangle in the mesh
+    pub fn material(&self, triangle: usize) -> &MaterialEnum {
+        return self.material.as_ref().unwrap_or(&self.mesh.materials[self.mesh.triangles[triangle].material]);
+    }
+
+    /// Move a position from the mesh's space into the world
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        return self.transform.map_or(p, |(t, _)| t.point(p));
+    }
+
+    /// Move a normal from the mesh's space into the world
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        return self.transform.map_or(n, |(t, _)| t.normal(n));
+    }
+
+}
+
+impl Hittable for Instance {
+
+    /// The ray and the hit are both in the world
+    fn hit(&self, r: Ray) -> Hit {
+
+        // Left where it is with its own materials, the instance is just the mesh
+        if self.transform.is_none() && self.material.is_none() {
+            return self.mesh.hit(r);
+        }
+
+        // The ray is moved into the mesh's space instead of moving the mesh, t stays the same in both
+        let mut hit = self.mesh.intersect(self.transform.map_or(r, |(_, inverse)| inverse.ray(r)));
+        if hit.t <= 0.0 {
+            return hit;
+        }
+        hit.material = self.material(hit.triangle).clone();
+        let (mut normal, mut tangent) = self.mesh.surface(&mut hit);
+
+        // Everything is shaded in the world, so textures and normal maps see the mesh the way it is placed
+        if let Some((transform, _)) = self.transform {
+            hit.at = r.at(hit.t);
+            hit.geometric_normal = transform.normal(hit.geometric_normal);
+            hit.uv_derivatives = hit.uv_derivatives.map(|(dpdu, dpdv)| (transform.vector(dpdu), transform.vector(dpdv)));
+            normal = transform.normal(normal);
+            tangent = transform.vector(tangent);
+        }
+        hit.set_face_normal(r, normal, tangent);
+        return hit;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.mesh.bounds()?;
+        return Some(self.transform.map_or(bounds, |(t, _)| t.bounds(bounds)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattened_instance_is_an_error() {
+        let mesh = Mesh::new().into_shared();
+        assert!(Instance::new(mesh.clone(), Transform::scale(Vec3::new(2.0, 0.0, 2.0))).is_err());
+        assert!(Instance::new(mesh.clone(), Transform::scale(Vec3::new(2.0, 2.0, 2.0))).is_ok());
+        assert!(Instance::new(mesh, Transform::identity()).is_ok());
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{vec3::{Vec3, dot, cross, unit_vector}, random::random_double};
+
+/// Single emissive triangle, light can be sampled from anywhere on it
+#[derive(Copy, Clone, Debug)]
+pub struct AreaLight {
+    /// The 3 points of the triangle
+    pub points: [Vec3; 3],
+    /// The side light leaves from
+    pub normal: Vec3,
+    /// Radiance given off
+    pub emission: Vec3,
+    /// Surface area of the triangle
+    pub area: f64
+}
+
+/// Light arriving at a point from a sampled light
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample {
+    /// Unit vector from the point towards the light
+    pub direction: Vec3,
+    /// Distance from the point to the light
+    pub distance: f64,
+    /// Radiance leaving the light towards the point
+    pub emission: Vec3,
+    /// Probability density of picking this direction, per unit solid angle
+    pub pdf: f64
+}
+
+impl AreaLight {
+
+    /// Create a light from a triangle
+    /// # Arguments
+    /// * 'points' - Corners of the triangle
+    /// * 'normal' - The triangle's normal, light leaves the side it points out of
+    /// * 'emission' - Radiance given off
+    pub fn new(points: [Vec3; 3], normal: Vec3, emission: Vec3) -> AreaLight {
+        let area = cross(points[1] - points[0], points[2] - points[0]).length() * 0.5;
+        return AreaLight { points, normal, emission, area };
+    }
+
+    /// Power of the light, lights are picked in proportion to it
+    pub fn power(&self) -> f64 {
+        return self.emission.luminance() * self.area;
+    }
+
+    /// Pick a uniformly distributed random point on the triangle
+    pub fn sample_point(&self) -> Vec3 {
+        let su = random_double().sqrt();
+        let b0 = 1.0 - su;
+        let b1 = random_double() * su;
+        return self.points[0] * b0 + self.points[1] * b1 + self.points[2] * (1.0 - b0 - b1);
+    }
+}
+
+/// Every area light in the world, with what's needed to pick one at random
+#[derive(Clone, Debug)]
+pub struct LightList {
+    pub lights: Vec<AreaLight>,
+    /// Running total of the lights' power, used to pick them in proportion to it
+    cdf: Vec<f64>,
+    /// Power of every light added up
+    total_power: f64
+}
+
+impl LightList {
+
+    /// Create an empty light list
+    pub fn new() -> LightList {
+        return LightList { lights: Vec::new(), cdf: Vec::new(), total_power: 0.0 };
+    }
+
+    /// Add a light to the list
+    /// # Arguments
+    /// * 'light' - Light to add, lights giving off nothing are skipped
+    pub fn add(&mut self, light: AreaLight) {
+        let power = light.power();
+        if power <= 0.0 {
+            return;
+        }
+        self.total_power += power;
+        self.cdf.push(self.total_power);
+        self.lights.push(light);
+    }
+
+    /// Check if there are any lights to sample
+    pub fn is_empty(&self) -> bool {
+        return self.lights.is_empty();
+    }
+
+    /// Pick a random point on a random light as seen from a point
+    /// # Arguments
+    /// * 'p' - Point the light is seen from
+    /// # Returns
+    /// * The light sample, None if there are no lights or the back of the light was picked
+    pub fn sample(&self, p: Vec3) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        // Brighter and bigger lights get picked more often
+        let target = random_double() * self.total_power;
+        let index = self.cdf.partition_point(|&c| c <= target).min(self.lights.len() - 1);
+        let light = &self.lights[index];
+
+        let to_light = light.sample_point() - p;
+        let distance_squared = to_light.length_squared();
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+
+        // Light only leaves the front of the triangle
+        let cosine = dot(-direction, light.normal);
+        if cosine <= 1e-8 {
+            return None;
+        }
+
+        return Some(LightSample {
+            direction,
+            distance,
+            emission: light.emission,
+            pdf: self.pdf_area(light.emission) * distance_squared / cosine
+        });
+    }
+
+    /// Probability density of sampling a point on a light, per unit area
+    /// Picking lights by power and points uniformly cancels out the area, leaving just the light's brightness
+    /// # Arguments
+    /// * 'emission' - Radiance of the light the point is on
+    pub fn pdf_area(&self, emission: Vec3) -> f64 {
+        if self.total_power <= 0.0 {
+            return 0.0;
+        }
+        return emission.luminance() / self.total_power;
+    }
+}
+
+/// Light given off from a single point in every direction
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// Light given off, falls off with the square of the distance
+    pub intensity: Vec3
+}
+
+/// Point light that only shines within a cone
+#[derive(Copy, Clone, Debug)]
+pub struct SpotLight {
+    pub position: Vec3,
+    /// Unit vector the cone points along
+    pub direction: Vec3,
+    /// Light given off along the cone's axis, falls off with the square of the distance
+    pub intensity: Vec3,
+    /// Cosine of the angle from the axis where the light starts fading out
+    cos_inner: f64,
+    /// Cosine of the angle from the axis past which there's no light at all
+    cos_outer: f64
+}
+
+/// Light arriving from the same direction everywhere, like the sun
+#[derive(Copy, Clone, Debug)]
+pub struct DirectionalLight {
+    /// Unit vector the light travels along
+    pub direction: Vec3,
+    /// Light arriving on a surface facing it, doesn't fall off with distance
+    pub irradiance: Vec3
+}
+
+impl PointLight {
+    /// Create a new point light
+    /// # Arguments
+    /// * 'position' - Where the light is
+    /// * 'intensity' - Light given off
+    pub fn new(position: Vec3, intensity: Vec3) -> PointLight {
+        return PointLight { position, intensity };
+    }
+}
+
+impl SpotLight {
+    /// Create a new spot light
+    /// # Arguments
+    /// * 'position' - Where the light is
+    /// * 'direction' - Direction the cone points along
+    /// * 'intensity' - Light given off along the cone's axis
+    /// * 'inner_angle' - Angle from the axis in degrees where the light starts fading out
+    /// * 'outer_angle' - Angle from the axis in degrees past which there's no light
+    /// # Returns
+    /// * The light, or a message if the angles don't make a cone
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, inner_angle: f64, outer_angle: f64) -> Result<SpotLight, String> {
+        if !(outer_angle > 0.0 && outer_angle < 180.0) {
+            return Err(format!("spot light outer_angle must be between 0 and 180 degrees, not {}", outer_angle));
+        }
+        if !(inner_angle >= 0.0 && inner_angle <= outer_angle) {
+            return Err(format!("spot light inner_angle must be between 0 and outer_angle ({}) degrees, not {}", outer_angle, inner_angle));
+        }
+        let cos_outer = outer_angle.to_radians().cos();
+        let cos_inner = inner_angle.to_radians().cos();
+        return Ok(SpotLight { position, direction: unit_vector(direction), intensity, cos_inner, cos_outer });
+    }
+
+    /// Calculate how much of the light leaves in a direction
+    /// # Arguments
+    /// * 'w' - Unit vector from the light outwards
+    /// # Returns
+    /// * 1.0 inside the inner cone, 0.0 outside the outer cone, smoothly blended in between
+    fn falloff(&self, w: Vec3) -> f64 {
+        let cosine = dot(w, self.direction);
+        if cosine >= self.cos_inner {
+            return 1.0;
+        }
+        if cosine <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cosine - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        return t * t * (3.0 - 2.0 * t);
+    }
+}
+
+impl DirectionalLight {
+    /// Create a new directional light
+    /// # Arguments
+    /// * 'direction' - Direction the light travels along, e.g. (0,-1,0) for a sun straight overhead
+    /// * 'irradiance' - Light arriving on a surface facing it
+    pub fn new(direction: Vec3, irradiance: Vec3) -> DirectionalLight {
+        return DirectionalLight { direction: unit_vector(direction), irradiance };
+    }
+}
+
+/// Store all the different types of lights without any geometry
+/// Nothing can hit them, they're only ever found by sampling them from a surface
+#[derive(Copy, Clone, Debug)]
+pub enum PunctualLight {
+    Point(PointLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight)
+}
+
+impl PunctualLight {
+
+    /// Calculate the light arriving at a point, ignoring anything in the way
+    /// # Arguments
+    /// * 'p' - Point being lit
+    /// # Returns
+    /// * The light arriving, None if the point is outside a spot light's cone
+    /// * There's only one direction light can come from, so the pdf is always 1.0
+    pub fn illuminate(&self, p: Vec3) -> Option<LightSample> {
+        match self {
+            PunctualLight::Point(light) => {
+                let to_light = light.position - p;
+                let distance_squared = to_light.length_squared();
+                let distance = distance_squared.sqrt();
+                return Some(LightSample {
+                    direction: to_light / distance,
+                    distance,
+                    emission: light.intensity / distance_squared,
+                    pdf: 1.0
+                });
+            },
+            PunctualLight::Spot(light) => {
+                let to_light = light.position - p;
+                let distance_squared = to_light.length_squared();
+                let distance = distance_squared.sqrt();
+                let direction = to_light / distance;
+                let falloff = light.falloff(-direction);
+                if falloff <= 0.0 {
+                    return None;
+                }
+                return Some(LightSample {
+                    direction,
+                    distance,
+                    emission: light.intensity * (falloff / distance_squared),
+                    pdf: 1.0
+                });
+            },
+            PunctualLight::Directional(light) => {
+                return Some(LightSample {
+                    direction: -light.direction,
+                    distance: f64::INFINITY,
+                    emission: light.irradiance,
+                    pdf: 1.0
+                });
+            }
+        }
+    }
+}
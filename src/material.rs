@@ -1,16 +1,25 @@
-use crate::{ray::Ray, hit::Hit, vec3::{Vec3, random_unit_vector, barycentric, unit_vector, reflect, dot, random_in_unit_sphere}};
+use std::f64::consts::PI;
+use crate::{ray::Ray, hit::Hit, texture::{Texture, NormalMap}, random::random_double, vec3::{Vec3, random_unit_vector, unit_vector, reflect, refract, dot, random_in_unit_sphere, orthonormal_basis, cross}};
 
 /// Store all the different types of materials
 #[derive(Clone, Debug)]
 pub enum MaterialEnum {
     Diffuse(Diffuse),
-    Metal(Metal)
+    Metal(Metal),
+    Dielectric(Dielectric),
+    Emissive(Emissive),
+    Principled(Principled)
 }
 
 /// Contains functions every material needs to be able to perform
 /// # Functions
 /// * 'scatter' - Tells the program how the ray should scatter based on the material 
-/// * 'get_albedo' - Return the objects albedo color
+/// * 'get_albedo' - Return the objects albedo color at a hit
+/// * 'emitted' - Light given off by the object, black unless it's a light source
+/// * 'is_specular' - Whether lights can't be sampled directly, the scattered direction is all that matters
+/// * 'eval' - How much light coming from a direction gets reflected back along the ray
+/// * 'pdf' - How likely scatter is to pick a direction
+/// * 'normal_map' - Normal or bump map perturbing the shading normal, if there is one
 pub trait Material {
     /// Determine how the ray will bounce off the object based on its material
     /// # Arguments
@@ -23,9 +32,53 @@ pub trait Material {
     fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool;
 
     /// Return the color of the object
+    /// # Arguments
+    /// * 'hit' - Information about what we hit, textured colors depend on where
     /// # Returns
     /// * Vec3 containing r,g,b values of the object in the x,y,z position
-    fn get_albedo(&self) -> Vec3;
+    fn get_albedo(&self, hit: &Hit) -> Vec3;
+
+    /// Return the light given off at a hit
+    /// # Arguments
+    /// * 'hit' - Information about what we hit
+    /// # Returns
+    /// * Emitted radiance, black for anything that isn't a light
+    fn emitted(&self, _hit: &Hit) -> Vec3 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+
+    /// Check if the material only scatters in a handful of directions, like a mirror or glass
+    /// Sampling a light directly would almost never line up with one of them, so it's skipped for these
+    fn is_specular(&self) -> bool {
+        return true;
+    }
+
+    /// Evaluate the material for light arriving from a given direction
+    /// # Arguments
+    /// * 'r' - The incoming ray
+    /// * 'hit' - Information about what we hit
+    /// * 'direction' - Unit vector pointing towards where the light comes from
+    /// # Returns
+    /// * Fraction of the light reflected back along the ray, cosine term included
+    fn eval(&self, _r: Ray, _hit: &Hit, _direction: Vec3) -> Vec3 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+
+    /// Probability density of scatter picking a direction
+    /// # Arguments
+    /// * 'r' - The incoming ray
+    /// * 'hit' - Information about what we hit
+    /// * 'direction' - Unit vector of the scattered direction
+    /// # Returns
+    /// * Density per unit solid angle
+    fn pdf(&self, _r: Ray, _hit: &Hit, _direction: Vec3) -> f64 {
+        return 0.0;
+    }
+
+    /// Return the normal or bump map applied to the shading normal
+    fn normal_map(&self) -> Option<&NormalMap> {
+        return None;
+    }
 }
 
 impl Material for MaterialEnum {
@@ -41,16 +94,102 @@ impl Material for MaterialEnum {
             MaterialEnum::Metal(mat) => {
                 return mat.scatter(r, hit, attenuation, scattered);
             }
+            MaterialEnum::Dielectric(mat) => {
+                return mat.scatter(r, hit, attenuation, scattered);
+            }
+            MaterialEnum::Emissive(mat) => {
+                return mat.scatter(r, hit, attenuation, scattered);
+            }
+            MaterialEnum::Principled(mat) => {
+                return mat.scatter(r, hit, attenuation, scattered);
+            }
         }
     }
-    fn get_albedo(&self) -> Vec3 {
+    fn get_albedo(&self, hit: &Hit) -> Vec3 {
         match self {
             MaterialEnum::Diffuse(mat) => {
-                return mat.get_albedo();
+                return mat.get_albedo(hit);
             },
             MaterialEnum::Metal(mat) => {
-                return mat.get_albedo();
+                return mat.get_albedo(hit);
+            },
+            MaterialEnum::Dielectric(mat) => {
+                return mat.get_albedo(hit);
+            },
+            MaterialEnum::Emissive(mat) => {
+                return mat.get_albedo(hit);
+            },
+            MaterialEnum::Principled(mat) => {
+                return mat.get_albedo(hit);
+            },
+        }
+    }
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        match self {
+            MaterialEnum::Emissive(mat) => {
+                return mat.emitted(hit);
+            },
+            _ => {
+                return Vec3::new(0.0, 0.0, 0.0);
+            }
+        }
+    }
+    fn is_specular(&self) -> bool {
+        match self {
+            MaterialEnum::Diffuse(mat) => {
+                return mat.is_specular();
+            },
+            MaterialEnum::Principled(mat) => {
+                return mat.is_specular();
             },
+            _ => {
+                return true;
+            }
+        }
+    }
+    fn eval(&self, r: Ray, hit: &Hit, direction: Vec3) -> Vec3 {
+        match self {
+            MaterialEnum::Diffuse(mat) => {
+                return mat.eval(r, hit, direction);
+            },
+            MaterialEnum::Metal(mat) => {
+                return mat.eval(r, hit, direction);
+            },
+            MaterialEnum::Principled(mat) => {
+                return mat.eval(r, hit, direction);
+            },
+            _ => {
+                return Vec3::new(0.0, 0.0, 0.0);
+            }
+        }
+    }
+    fn pdf(&self, r: Ray, hit: &Hit, direction: Vec3) -> f64 {
+        match self {
+            MaterialEnum::Diffuse(mat) => {
+                return mat.pdf(r, hit, direction);
+            },
+            MaterialEnum::Principled(mat) => {
+                return mat.pdf(r, hit, direction);
+            },
+            _ => {
+                return 0.0;
+            }
+        }
+    }
+    fn normal_map(&self) -> Option<&NormalMap> {
+        match self {
+            MaterialEnum::Diffuse(mat) => {
+                return mat.normal_map();
+            },
+            MaterialEnum::Metal(mat) => {
+                return mat.normal_map();
+            },
+            MaterialEnum::Principled(mat) => {
+                return mat.normal_map();
+            },
+            _ => {
+                return None;
+            }
         }
     }
 }
@@ -59,15 +198,17 @@ impl Material for MaterialEnum {
 #[derive(Clone, Debug)]
 pub struct Diffuse {
     /// The objects albedo color
-    pub albedo: Vec3
+    pub albedo: Texture,
+    /// Optional normal or bump map
+    pub normal_map: Option<NormalMap>
 }
 
 impl Diffuse {
     /// Create a new diffuse material
     /// # Arguments
-    /// * 'albedo' - Desired color
-    pub fn new(albedo: Vec3) -> Diffuse {
-        return Diffuse { albedo };
+    /// * 'albedo' - Desired color or texture
+    pub fn new(albedo: Texture) -> Diffuse {
+        return Diffuse { albedo, normal_map: None };
     }
 }
 
@@ -76,64 +217,68 @@ impl Material for Diffuse {
     // Scatter function for an object with a diffuse material
     fn scatter(&self, _r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
 
-        let mut scatter_direction: Vec3;
-
-        // If the object is smooth shaded
-        if hit.triangle.smooth {
-
-            // Calculate the barycentric coordinates
-            let bary = barycentric(hit.clone());
-
-            // Create a new ray that's scattered
-            // The ray bounces based on the interpolated normal and a random unit vector, which aims to simulate diffuse's rough look
-            scatter_direction = unit_vector(hit.triangle.normals[0] * bary.x + hit.triangle.normals[1] * bary.y + hit.triangle.normals[2] * bary.z) + random_unit_vector();
-
-            if scatter_direction.near_zero() {
-                // If we're close to zero, just set as the normal
-                scatter_direction = hit.triangle.normal;
-            }
+        // The ray bounces based on the normal and a random unit vector, which aims to simulate diffuse's rough look
+        // The normal is already interpolated if the object is smooth shaded
+        let mut scatter_direction = hit.normal + random_unit_vector();
 
-            // Set the current scattered ray based on the location the ray hit and the new direction
-            *scattered = Ray::new(hit.at, scatter_direction);
-            *attenuation = self.albedo; // Current objects color
+        if scatter_direction.near_zero() {
+            // If we're close to zero, just set as the normal
+            scatter_direction = hit.normal;
+        }
 
-        } else { // Not smooth shaded
+        // Set the current scattered ray based on the location the ray hit and the new direction
+        *scattered = Ray::new(hit.at, scatter_direction);
+        *attenuation = self.albedo.value(&hit); // Current objects color
+        return true;
+    }
 
-            // Use the single normal and bounce
-            scatter_direction = hit.triangle.normal + random_unit_vector();
+    // Simply return the albedo color
+    fn get_albedo(&self, hit: &Hit) -> Vec3 {
+        return self.albedo.value(hit);
+    }
 
-            if scatter_direction.near_zero() {
-                scatter_direction = hit.triangle.normal;
-            }
+    // Diffuse scatters in every direction, so lights can be sampled directly
+    fn is_specular(&self) -> bool {
+        return false;
+    }
 
-            *scattered = Ray::new(hit.at, scatter_direction);
-            *attenuation = self.albedo;
+    // Lambertian reflection, albedo / pi times the cosine of the angle to the normal
+    fn eval(&self, _r: Ray, hit: &Hit, direction: Vec3) -> Vec3 {
+        let cosine = dot(direction, hit.normal);
+        if cosine <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
         }
-        return true;
+        return self.albedo.value(hit) * (cosine / PI);
     }
 
-    // Simply return the albedo color
-    fn get_albedo(&self) -> Vec3 {
-        return self.albedo;
+    // Normal plus a random unit vector gives directions distributed by cosine / pi
+    fn pdf(&self, _r: Ray, hit: &Hit, direction: Vec3) -> f64 {
+        return (dot(direction, hit.normal) / PI).max(0.0);
+    }
+
+    fn normal_map(&self) -> Option<&NormalMap> {
+        return self.normal_map.as_ref();
     }
 }
 
 /// Simple metal material
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Metal {
     /// Albedo color
-    pub albedo: Vec3,
+    pub albedo: Texture,
     /// How smooth the metal is
-    pub smoothness: f64
+    pub smoothness: f64,
+    /// Optional normal or bump map
+    pub normal_map: Option<NormalMap>
 }
 
 impl Metal {
     /// Create a new metal material
     /// # Arguments
-    /// * 'albedo' - Desired color
+    /// * 'albedo' - Desired color or texture
     /// * 'smoothness' - How smooth metal is (0.0 is mirror, 1.0 not smooth at all)
-    pub fn new(albedo: Vec3, smoothness: f64) -> Metal {
-        Metal {albedo, smoothness }
+    pub fn new(albedo: Texture, smoothness: f64) -> Metal {
+        Metal { albedo, smoothness, normal_map: None }
     }
 
 }
@@ -143,37 +288,363 @@ impl Material for Metal {
     // Scatter for a metal material
     fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
 
-        // Will contain the reflected direction
-        let reflected: Vec3;
+        // Call reflect function based on the input ray direction and the normal
+        // Multiply by a random in unit sphere and smoothness to change how smooth the reflection is
+        let reflected = reflect(unit_vector(r.direction), hit.normal + (random_in_unit_sphere() * self.smoothness));
+
+        // Set the new scattered direction based on the reflection
+        *scattered = Ray::new(hit.at, reflected);
+        *attenuation = self.albedo.value(&hit);
+
+        // Make sure the scattered direction is in a similar direction as the normal
+        return dot(scattered.direction, hit.normal) > 0.0;
+    }
+
+    fn get_albedo(&self, hit: &Hit) -> Vec3 {
+        return self.albedo.value(hit);
+    }
+
+    // Still counts as specular, scattering towards an area light already finds it often enough
+    // Point, spot and directional lights can't be hit though, so rough metal gets a highlight from them here
+    fn eval(&self, r: Ray, hit: &Hit, direction: Vec3) -> Vec3 {
+        // A perfect mirror reflects a point light in exactly one direction, which never lines up
+        if self.smoothness <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let cosine = dot(direction, hit.normal);
+        if cosine <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+
+        // Phong lobe around the mirror direction, about as wide as the spread scatter gives
+        let exponent = (2.0 / (self.smoothness * self.smoothness) - 2.0).max(0.0);
+        let reflected = reflect(unit_vector(r.direction), hit.normal);
+        let alignment = dot(reflected, direction).max(0.0);
+        return self.albedo.value(hit) * ((exponent + 2.0) / (2.0 * PI) * alignment.powf(exponent) * cosine);
+    }
+
+    fn normal_map(&self) -> Option<&NormalMap> {
+        return self.normal_map.as_ref();
+    }
+}
+
+/// Clear material that refracts light, such as glass or water
+#[derive(Copy, Clone, Debug)]
+pub struct Dielectric {
+    /// Tint applied to light passing through or reflecting off, white for clear glass
+    pub albedo: Vec3,
+    /// Index of refraction, 1.0 is air, 1.5 is glass, 2.4 is diamond
+    pub ir: f64
+}
+
+impl Dielectric {
+    /// Create a new dielectric material
+    /// # Arguments
+    /// * 'albedo' - Tint color
+    /// * 'ir' - Index of refraction
+    pub fn new(albedo: Vec3, ir: f64) -> Dielectric {
+        return Dielectric { albedo, ir };
+    }
+
+    /// Calculate how much light reflects rather than refracts
+    /// # Arguments
+    /// * 'cosine' - Cosine of the angle between the incoming ray and the normal
+    /// * 'ratio' - Ratio of the indices of refraction on either side of the surface
+    /// # Credit
+    /// * Schlick's approximation of the Fresnel equations
+    /// * <https://en.wikipedia.org/wiki/Schlick%27s_approximation>
+    fn reflectance(cosine: f64, ratio: f64) -> f64 {
+        let r0 = ((1.0 - ratio) / (1.0 + ratio)).powi(2);
+        return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
+    }
+}
+
+impl Material for Dielectric {
+
+    // Scatter for a dielectric material, the ray either reflects or refracts
+    fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+
+        // Entering the object goes from air into the material, leaving goes the other way
+        let ratio = if hit.front_face { 1.0 / self.ir } else { self.ir };
 
-        // If the object is smooth shaded
-        if hit.triangle.smooth {
+        let unit_direction = unit_vector(r.direction);
+        let cos_theta = dot(-unit_direction, hit.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-            // Calculate barycentric coordinates
-            let bary = barycentric(hit.clone());
+        // Past the critical angle there's no solution to Snell's law, all light is reflected
+        let cannot_refract = ratio * sin_theta > 1.0;
 
-            // Call reflect function based on the input ray direction and the interpolated normal
-            // Multiply by a random in unit sphere and smoothness to change how smooth the reflection is
-            reflected = reflect(unit_vector(r.direction), unit_vector(hit.triangle.normals[0] * bary.x + hit.triangle.normals[1] * bary.y + hit.triangle.normals[2] * bary.z) + (random_in_unit_sphere() * self.smoothness));
+        // Otherwise pick reflection with the probability the Fresnel equations give
+        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, ratio) > random_double() {
+            reflect(unit_direction, hit.normal)
+        } else {
+            refract(unit_direction, hit.normal, ratio)
+        };
 
-            // Set the new scattered direction based on the reflection
-            *scattered = Ray::new(hit.at, reflected);
-            *attenuation = self.albedo;
+        *scattered = Ray::new(hit.at, direction);
+        *attenuation = self.albedo;
+        return true;
+    }
 
-            // Make sure the scattered direction is in a similar direction as the normals of each vertex
-            return dot(scattered.direction, hit.triangle.normals[0]) > 0.0 || dot(scattered.direction, hit.triangle.normals[1]) > 0.0 || dot(scattered.direction, hit.triangle.normals[2]) > 0.0;
+    fn get_albedo(&self, _hit: &Hit) -> Vec3 {
+        return self.albedo;
+    }
+}
+
+/// Material that gives off light, turns any mesh into a light source
+#[derive(Copy, Clone, Debug)]
+pub struct Emissive {
+    /// Color of the light
+    pub color: Vec3,
+    /// Brightness the color is multiplied by
+    pub strength: f64
+}
+
+impl Emissive {
+    /// Create a new emissive material
+    /// # Arguments
+    /// * 'color' - Light color
+    /// * 'strength' - Brightness
+    pub fn new(color: Vec3, strength: f64) -> Emissive {
+        return Emissive { color, strength };
+    }
+}
+
+impl Material for Emissive {
+
+    // Lights absorb whatever hits them, they only emit
+    fn scatter(&self, _r: Ray, _hit: Hit, _attenuation: &mut Vec3, _scattered: &mut Ray) -> bool {
+        return false;
+    }
+
+    fn get_albedo(&self, _hit: &Hit) -> Vec3 {
+        return self.color;
+    }
+
+    // Light only leaves the front of the surface, the side the normals point out of
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        if !hit.front_face {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        return self.color * self.strength;
+    }
+}
+
+/// Physically based material following glTF's metallic-roughness model
+/// A diffuse base under a GGX specular layer, blending into a pure GGX conductor as metallic goes up
+/// # Credit
+/// * glTF 2.0 specification, Appendix B, <https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#appendix-b-brdf-implementation>
+/// * Heitz, "Sampling the GGX Distribution of Visible Normals", <https://jcgt.org/published/0007/04/01/>
+#[derive(Clone, Debug)]
+pub struct Principled {
+    /// Diffuse color of dielectrics, reflection color of metals
+    pub base_color: Texture,
+    /// 0.0 is a dielectric like plastic, 1.0 is a metal
+    /// Textures are read as gray, using their luminance
+    pub metallic: Texture,
+    /// Perceptual roughness, 0.0 is a mirror finish and 1.0 is completely rough
+    /// Textures are read as gray, using their luminance
+    pub roughness: Texture,
+    /// Strength of the specular reflection on dielectrics, 0.5 gives the usual 4% of glass and plastic
+    pub specular: f64,
+    /// Optional normal or bump map
+    pub normal_map: Option<NormalMap>
+}
+
+impl Principled {
+    /// Create a new principled material
+    /// # Arguments
+    /// * 'base_color' - Base color or texture
+    /// * 'metallic' - How metallic the surface is, 0.0 to 1.0, or a texture of it
+    /// * 'roughness' - How rough the surface is, 0.0 to 1.0, or a texture of it
+    /// * 'specular' - Specular strength of dielectrics, 0.0 to 1.0
+    pub fn new(base_color: Texture, metallic: Texture, roughness: Texture, specular: f64) -> Principled {
+        return Principled {
+            base_color,
+            metallic,
+            roughness,
+            specular: specular.clamp(0.0, 1.0),
+            normal_map: None
+        };
+    }
 
+    /// GGX alpha at a hit, the square of the roughness
+    /// Kept away from 0.0, a perfect mirror's distribution is infinitely thin
+    fn alpha(&self, hit: &Hit) -> f64 {
+        let roughness = self.roughness.value(hit).luminance().clamp(0.0, 1.0);
+        return (roughness * roughness).max(1e-3);
+    }
+
+    /// How metallic the surface is at a hit
+    fn metallic(&self, hit: &Hit) -> f64 {
+        return self.metallic.value(hit).luminance().clamp(0.0, 1.0);
+    }
+
+    /// Reflectance looking straight at the surface
+    /// # Arguments
+    /// * 'base_color' - Base color at the hit
+    /// * 'metallic' - How metallic the surface is at the hit
+    fn f0(&self, base_color: Vec3, metallic: f64) -> Vec3 {
+        let dielectric = Vec3::new(1.0, 1.0, 1.0) * (0.08 * self.specular);
+        return dielectric * (1.0 - metallic) + base_color * metallic;
+    }
+
+    /// Schlick's approximation of the Fresnel equations
+    /// # Arguments
+    /// * 'f0' - Reflectance looking straight at the surface
+    /// * 'cosine' - Cosine of the angle to the normal
+    fn fresnel(f0: Vec3, cosine: f64) -> Vec3 {
+        let t = (1.0 - cosine).clamp(0.0, 1.0).powi(5);
+        return f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * t;
+    }
+
+    /// GGX normal distribution
+    /// # Arguments
+    /// * 'alpha' - GGX alpha
+    /// * 'cosine' - Cosine of the angle between the microfacet normal and the surface normal
+    fn distribution(alpha: f64, cosine: f64) -> f64 {
+        let a2 = alpha * alpha;
+        let d = cosine * cosine * (a2 - 1.0) + 1.0;
+        return a2 / (PI * d * d);
+    }
+
+    /// Smith's lambda for GGX, how much of the surface hides itself when seen from a direction
+    /// # Arguments
+    /// * 'alpha' - GGX alpha
+    /// * 'cosine' - Cosine of the angle between the direction and the surface normal
+    fn lambda(alpha: f64, cosine: f64) -> f64 {
+        let cos2 = cosine * cosine;
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        return ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) * 0.5;
+    }
+
+    /// Probability of sampling the specular lobe rather than the diffuse one
+    /// # Arguments
+    /// * 'cos_view' - Cosine of the angle between the view direction and the normal
+    /// * 'base_color' - Base color at the hit
+    /// * 'metallic' - How metallic the surface is at the hit
+    fn specular_probability(&self, cos_view: f64, base_color: Vec3, metallic: f64) -> f64 {
+        let specular = Principled::fresnel(self.f0(base_color, metallic), cos_view).luminance();
+        let diffuse = (1.0 - metallic) * base_color.luminance() * (1.0 - specular);
+        if specular + diffuse <= 0.0 {
+            return 1.0;
+        }
+        return (specular / (specular + diffuse)).clamp(0.1, 1.0);
+    }
+
+    /// Pick a microfacet normal, weighted by how much of each is visible from the view direction
+    /// # Arguments
+    /// * 'alpha' - GGX alpha
+    /// * 'v' - Unit vector pointing away from the surface towards the viewer
+    /// * 'n' - Surface normal
+    fn sample_microfacet(alpha: f64, v: Vec3, n: Vec3) -> Vec3 {
+        // Work in a frame where the normal is the z axis
+        let (t, b) = orthonormal_basis(n);
+        let local = Vec3::new(dot(v, t), dot(v, b), dot(v, n));
+
+        // Stretch the view so the distribution becomes a hemisphere
+        let vh = unit_vector(Vec3::new(alpha * local.x, alpha * local.y, local.z));
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 { Vec3::new(-vh.y, vh.x, 0.0) / len2.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = cross(vh, t1);
+
+        // Pick a point on the projected hemisphere, squeezed to the part visible from the view
+        let r = random_double().sqrt();
+        let phi = 2.0 * PI * random_double();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        // Unstretch back to the original distribution
+        let m = unit_vector(Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(0.0)));
+        return t * m.x + b * m.y + n * m.z;
+    }
+}
+
+impl Material for Principled {
+
+    // Pick between the diffuse and specular lobe, then weight by how likely the direction was under both
+    fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+        let v = -unit_vector(r.direction);
+        let cos_view = dot(v, hit.normal);
+        if cos_view <= 0.0 {
+            return false;
+        }
+
+        let direction = if random_double() < self.specular_probability(cos_view, self.base_color.value(&hit), self.metallic(&hit)) {
+            reflect(-v, Principled::sample_microfacet(self.alpha(&hit), v, hit.normal))
         } else {
-            reflected = reflect(unit_vector(r.direction), hit.triangle.normal + (random_in_unit_sphere() * self.smoothness));
-            *scattered = Ray::new(hit.at, reflected);
-            *attenuation = self.albedo;
 
-            // Make sure the direction is similar to the triangle's normal
-            return dot(scattered.direction, hit.triangle.normal) > 0.0;
+            // Like Diffuse, fall back to the normal rather than normalizing a zero vector
+            let direction = hit.normal + random_unit_vector();
+            if direction.near_zero() { hit.normal } else { unit_vector(direction) }
+        };
+
+        let pdf = self.pdf(r, &hit, direction);
+        if dot(direction, hit.normal) <= 0.0 || pdf <= 0.0 {
+            return false;
         }
+
+        *scattered = Ray::new(hit.at, direction);
+        *attenuation = self.eval(r, &hit, direction) / pdf;
+        return true;
     }
 
-    fn get_albedo(&self) -> Vec3 {
-        return self.albedo;
+    fn get_albedo(&self, hit: &Hit) -> Vec3 {
+        return self.base_color.value(hit);
+    }
+
+    // Even with 0.0 roughness the distribution keeps some width, so lights can always be sampled
+    fn is_specular(&self) -> bool {
+        return false;
+    }
+
+    fn eval(&self, r: Ray, hit: &Hit, direction: Vec3) -> Vec3 {
+        let v = -unit_vector(r.direction);
+        let n = hit.normal;
+        let cos_view = dot(v, n);
+        let cos_light = dot(direction, n);
+        if cos_view <= 0.0 || cos_light <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+
+        let base_color = self.base_color.value(hit);
+        let metallic = self.metallic(hit);
+        let h = unit_vector(v + direction);
+        let fresnel = Principled::fresnel(self.f0(base_color, metallic), dot(v, h));
+
+        // Light the specular layer reflects never reaches the diffuse base
+        let diffuse = (Vec3::new(1.0, 1.0, 1.0) - fresnel) * base_color * ((1.0 - metallic) / PI * cos_light);
+
+        // Cook-Torrance with the height correlated Smith shadowing term, the cosine cancels out
+        let alpha = self.alpha(hit);
+        let shadowing = 1.0 / (1.0 + Principled::lambda(alpha, cos_view) + Principled::lambda(alpha, cos_light));
+        let specular = fresnel * (Principled::distribution(alpha, dot(n, h)) * shadowing / (4.0 * cos_view));
+
+        return diffuse + specular;
+    }
+
+    fn pdf(&self, r: Ray, hit: &Hit, direction: Vec3) -> f64 {
+        let v = -unit_vector(r.direction);
+        let n = hit.normal;
+        let cos_view = dot(v, n);
+        let cos_light = dot(direction, n);
+        if cos_view <= 0.0 || cos_light <= 0.0 {
+            return 0.0;
+        }
+
+        // Visible normal sampling picks reflected directions with G1 * D / (4 * cos_view)
+        let alpha = self.alpha(hit);
+        let h = unit_vector(v + direction);
+        let specular = Principled::distribution(alpha, dot(n, h)) / ((1.0 + Principled::lambda(alpha, cos_view)) * 4.0 * cos_view);
+        let diffuse = cos_light / PI;
+
+        let p = self.specular_probability(cos_view, self.base_color.value(hit), self.metallic(hit));
+        return p * specular + (1.0 - p) * diffuse;
     }
-}
\ No newline at end of file
+
+    fn normal_map(&self) -> Option<&NormalMap> {
+        return self.normal_map.as_ref();
+    }
+}
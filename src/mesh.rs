@@ -1,316 +1,621 @@
-use std::{fs::File, io::{BufReader, BufRead}};
-use crate::{triangle::Triangle, ray::Ray, hit::Hit, vec3::{Vec3, unit_vector}, material::{MaterialEnum, Diffuse}};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use crate::{triangle::Triangle, ray::Ray, hit::Hit, vec3::{Vec3, unit_vector, cross, barycentric}, material::{MaterialEnum, Diffuse}, aabb::Aabb, bvh::Bvh,
+    texture::Texture, mtl::load_mtl, transform::Transform, hittable::Hittable};
 
 /// Mesh struct
+/// Vertices are stored once in arrays of the same length, triangles index into them
 #[derive(Clone, Debug)]
 pub struct Mesh {
+    /// Position of every vertex
+    pub positions: Vec<Vec3>,
+    /// Normal of every vertex
+    pub normals: Vec<Vec3>,
+    /// Texture coordinates of every vertex, u and v in x and y
+    pub uvs: Vec<Vec3>,
+    /// Color of every vertex, white unless the mesh file gives vertex colors
+    pub colors: Vec<Vec3>,
+    /// Tangent of every vertex used by a smooth shaded triangle, the direction u increases along, see compute_tangents
+    pub tangents: Vec<Vec3>,
     /// All of the triangles in a mesh
     pub triangles: Vec<Triangle>,
-    /// The mesh's material
-    pub material: MaterialEnum
+    /// The mesh's materials, each triangle picks one by index
+    /// The first is the default for triangles nothing was assigned to
+    pub materials: Vec<MaterialEnum>,
+    /// Acceleration structure over the triangles, see build_bvh
+    bvh: Bvh
 }
 
 impl Mesh {
     /// Create a new empty mesh
     /// # Default Values
+    /// * 'positions, normals, uvs, colors, tangents' - Empty Rust vecs
     /// * 'triangles' - Empty Rust vec
-    /// * 'material' - White diffuse material
+    /// * 'materials' - White diffuse material
     pub fn new() -> Mesh {
         return Mesh { 
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            tangents: Vec::new(),
             triangles: Vec::new(),
-            material: MaterialEnum::Diffuse(Diffuse::new(Vec3::new(1.0, 1.0, 1.0)))
+            materials: vec![MaterialEnum::Diffuse(Diffuse::new(Texture::Solid(Vec3::new(1.0, 1.0, 1.0))))],
+            bvh: Bvh::new_empty()
         };
     }
 
-    /// Create a mesh with an already established Vec of triangles
+    /// Give the whole mesh a single material, replacing any assigned per triangle
     /// # Arguments
-    /// * 'trigs' - Rust vec of triangles
-    pub fn new_mesh(trigs: Vec<Triangle>) -> Mesh {
-        Mesh { triangles: trigs, material: MaterialEnum::Diffuse(Diffuse::new(Vec3::new(0.5, 0.5, 0.5))) }
+    /// * 'material' - Material every triangle will use
+    pub fn set_material(&mut self, material: MaterialEnum) {
+        self.materials = vec![material];
+        for trig in self.triangles.iter_mut() {
+            trig.material = 0;
+        }
     }
 
-    /// Create add triangles to a mesh
+    /// Add a vertex to the mesh
     /// # Arguments
-    /// * 'trig' - Single triangle to add
+    /// * 'position' - Where the vertex is
+    /// * 'normal' - Normal at the vertex
+    /// * 'uv' - Texture coordinates at the vertex
+    /// * 'color' - Color at the vertex
+    /// # Returns
+    /// * Index of the new vertex, for use in a triangle
+    pub fn add_vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec3, color: Vec3) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.colors.push(color);
+        self.tangents.push(Vec3::new(0.0, 0.0, 0.0));
+        return (self.positions.len() - 1) as u32;
+    }
+
+    /// Add a triangle to the mesh
+    /// # Arguments
+    /// * 'trig' - Single triangle to add, its vertices must already be in the mesh
     pub fn add(&mut self, trig: Triangle) {
         self.triangles.push(trig);
     }
 
-    /// Translate a mesh
+    /// Geometric normal of a triangle, following its winding order
+    /// Decides which side of the triangle was hit and which way it emits, whatever normals its vertices have
     /// # Arguments
-    /// * 'd' - Vec3 which contains the x,y,z directions to translate
-    pub fn translate(&mut self, d: Vec3) {
+    /// * 'trig' - A triangle of this mesh
+    pub fn face_normal(&self, trig: &Triangle) -> Vec3 {
+        let points = trig.points(&self.positions);
+        return unit_vector(cross(points[1] - points[0], points[2] - points[0]));
+    }
 
-        // Loop over each triangle in the mesh and simply add x,y,z to the points to translate
-        for trig in self.triangles.iter_mut() {
-            for point in trig.points.iter_mut() {
-                *point = Vec3::new(
-                    point.x + d.x,
-                    point.y + d.y,
-                    point.z + d.z);
-            }
+    /// Calculate how the position changes along the texture coordinates of a triangle
+    /// # Arguments
+    /// * 'trig' - A triangle of this mesh
+    /// # Returns
+    /// * Change in position per unit of u and per unit of v, None if the texture coordinates are degenerate
+    pub fn uv_derivatives(&self, trig: &Triangle) -> Option<(Vec3, Vec3)> {
+        let points = trig.points(&self.positions);
+        let uvs = trig.points(&self.uvs);
+        let dp1 = points[1] - points[0];
+        let dp2 = points[2] - points[0];
+        let duv1 = uvs[1] - uvs[0];
+        let duv2 = uvs[2] - uvs[0];
+
+        // Solve the 2x2 system mapping uv deltas to position deltas
+        let determinant = duv1.x * duv2.y - duv1.y * duv2.x;
+        if determinant.abs() < 1e-12 {
+            return None;
         }
+        let inverse = 1.0 / determinant;
+        let dpdu = (dp1 * duv2.y - dp2 * duv1.y) * inverse;
+        let dpdv = (dp2 * duv1.x - dp1 * duv2.x) * inverse;
+        return Some((dpdu, dpdv));
     }
 
-    /// Scale a mesh
-    /// # Arguments
-    /// * 'c' - Amount to scale
-    pub fn scale(&mut self, c: f64) {
+    /// Build the BVH over the mesh's triangles
+    /// Must be called again after the triangles change, into_shared takes care of this
+    pub fn build_bvh(&mut self) {
+        let bounds: Vec<Aabb> = self.triangles.iter().map(|t| t.bounds(&self.positions)).collect();
+        self.bvh = Bvh::new(&bounds);
+    }
 
-        // Loop over each triangle in the mesh and scale the points
-        for trig in self.triangles.iter_mut() {
-            for point in trig.points.iter_mut() {
-                *point = Vec3::new(
-                    point.x * c,
-                    point.y * c,
-                    point.z * c
-                );
+    /// Calculate the per vertex tangents of smooth shaded triangles
+    /// A vertex's tangent is averaged over every smooth triangle sharing it
+    pub fn compute_tangents(&mut self) {
+        self.tangents = vec![Vec3::new(0.0, 0.0, 0.0); self.positions.len()];
+
+        // Add up each triangle's tangent, weighted by its area, at each of its corners
+        for trig in self.triangles.iter().filter(|t| t.smooth) {
+            if let Some((dpdu, _)) = self.uv_derivatives(trig) {
+                let points = trig.points(&self.positions);
+                let area = cross(points[1] - points[0], points[2] - points[0]).length();
+                let tangent = unit_vector(dpdu) * area;
+                for v in trig.vertices {
+                    self.tangents[v as usize] = self.tangents[v as usize] + tangent;
+                }
+            }
+        }
+
+        // Left as zero when there's nothing to average, the triangle's own tangent gets used instead
+        for tangent in self.tangents.iter_mut() {
+            if !tangent.near_zero() {
+                *tangent = unit_vector(*tangent);
             }
         }
     }
 
-    /// Rotate a mesh
+    /// Move every vertex of the mesh, once
     /// # Arguments
-    /// * 'r' - Vec3 in degrees NOT radians
-    pub fn rotate(&mut self, r: Vec3) {
-
-        // Must convert to radians
-        let theta_x = r.x.to_radians();
-        let theta_y = r.y.to_radians();
-        let theta_z = r.z.to_radians();
+    /// * 't' - Transform to apply, normals and tangents follow it
+    pub fn transform(&mut self, t: &Transform) {
+        for point in self.positions.iter_mut() {
+            *point = t.point(*point);
+        }
+        for normal in self.normals.iter_mut() {
+            *normal = t.normal(*normal);
+        }
 
-        // Need to rotate each triangle in the mesh
-        for trig in self.triangles.iter_mut() {
+        // Left as zero where there's nothing to average, see compute_tangents
+        for tangent in self.tangents.iter_mut().filter(|t| !t.near_zero()) {
+            *tangent = unit_vector(t.vector(*tangent));
+        }
 
-            // First, let's rotate the normals
-            // Rotate on x
-            trig.normal = Vec3::new(
-                trig.normal.x,
-                trig.normal.y * theta_x.cos() - trig.normal.z * theta_x.sin(),
-                trig.normal.y * theta_x.sin() + trig.normal.z * theta_x.cos()
-            );
-
-            // If the triangle is smoothly shaded, it will have normals for each vertex
-            // Rotate each of the normals. If not smooth shaded, this doesn't do much
-            trig.normals[0] = Vec3::new(
-                trig.normals[0].x,
-                trig.normals[0].y * theta_x.cos() - trig.normals[0].z * theta_x.sin(),
-                trig.normals[0].y * theta_x.sin() + trig.normals[0].z * theta_x.cos()
-            );
-            trig.normals[1] = Vec3::new(
-                trig.normals[1].x,
-                trig.normals[1].y * theta_x.cos() - trig.normals[1].z * theta_x.sin(),
-                trig.normals[1].y * theta_x.sin() + trig.normals[1].z * theta_x.cos()
-            );
-            trig.normals[2] = Vec3::new(
-                trig.normals[2].x,
-                trig.normals[2].y * theta_x.cos() - trig.normals[2].z * theta_x.sin(),
-                trig.normals[2].y * theta_x.sin() + trig.normals[2].z * theta_x.cos()
-            );
-
-            // EACH time we rotate, we must normalize
-            trig.normal = unit_vector(trig.normal);
-            trig.normals[0] = unit_vector(trig.normals[0]);
-            trig.normals[1] = unit_vector(trig.normals[1]);
-            trig.normals[2] = unit_vector(trig.normals[2]);
-
-            // Rotate on y
-            trig.normal = Vec3::new(
-                trig.normal.x * theta_y.cos() + trig.normal.z * theta_y.sin(),
-                trig.normal.y,
-                -trig.normal.x * theta_y.sin() + trig.normal.z * theta_y.cos()
-            );
-            trig.normals[0] = Vec3::new(
-                trig.normals[0].x * theta_y.cos() + trig.normals[0].z * theta_y.sin(),
-                trig.normals[0].y,
-                -trig.normals[0].x * theta_y.sin() + trig.normals[0].z * theta_y.cos()
-            );
-            trig.normals[1] = Vec3::new(
-                trig.normals[1].x * theta_y.cos() + trig.normals[1].z * theta_y.sin(),
-                trig.normals[1].y,
-                -trig.normals[1].x * theta_y.sin() + trig.normals[1].z * theta_y.cos()
-            );
-            trig.normals[2] = Vec3::new(
-                trig.normals[2].x * theta_y.cos() + trig.normals[2].z * theta_y.sin(),
-                trig.normals[2].y,
-                -trig.normals[2].x * theta_y.sin() + trig.normals[2].z * theta_y.cos()
-            );
-            trig.normal = unit_vector(trig.normal);
-            trig.normals[0] = unit_vector(trig.normals[0]);
-            trig.normals[1] = unit_vector(trig.normals[1]);
-            trig.normals[2] = unit_vector(trig.normals[2]);
-
-            // Rotate on z
-            trig.normal = Vec3::new(
-                trig.normal.x * theta_z.cos() - trig.normal.y * theta_z.sin(),
-                trig.normal.x * theta_z.sin() + trig.normal.y * theta_z.cos(),
-                trig.normal.z
-            );
-            trig.normals[0] = Vec3::new(
-                trig.normals[0].x * theta_z.cos() - trig.normals[0].y * theta_z.sin(),
-                trig.normals[0].x * theta_z.sin() + trig.normals[0].y * theta_z.cos(),
-                trig.normals[0].z
-            );
-            trig.normals[1] = Vec3::new(
-                trig.normals[1].x * theta_z.cos() - trig.normals[1].y * theta_z.sin(),
-                trig.normals[1].x * theta_z.sin() + trig.normals[1].y * theta_z.cos(),
-                trig.normals[1].z
-            );
-            trig.normals[2] = Vec3::new(
-                trig.normals[2].x * theta_z.cos() - trig.normals[2].y * theta_z.sin(),
-                trig.normals[2].x * theta_z.sin() + trig.normals[2].y * theta_z.cos(),
-                trig.normals[2].z
-            );
-            trig.normal = unit_vector(trig.normal);
-            trig.normals[0] = unit_vector(trig.normals[0]);
-            trig.normals[1] = unit_vector(trig.normals[1]);
-            trig.normals[2] = unit_vector(trig.normals[2]);
-
-            // Now rotate the individual points
-            for point in trig.points.iter_mut() {
-
-                // Rotate on x.
-                *point = Vec3::new(
-                    point.x,
-                    point.y * theta_x.cos() - point.z * theta_x.sin(),
-                    point.y * theta_x.sin() + point.z * theta_x.cos()
-                );
-
-                // Rotate on y.
-                *point = Vec3::new(
-                    point.x * theta_y.cos() + point.z * theta_y.sin(),
-                    point.y,
-                    -point.x * theta_y.sin() + point.z * theta_y.cos()
-                );
-
-                // Rotate on z.
-                *point = Vec3::new(
-                    point.x * theta_z.cos() - point.y * theta_z.sin(),
-                    point.x * theta_z.sin() + point.y * theta_z.cos(),
-                    point.z
-                );
+        // A mirror turns the winding inside out, swap two corners so face_normal agrees with the vertex normals again
+        // The bitangent's handedness is worked out from the mirrored texture coordinates at each hit, see Hit::set_face_normal
+        if t.determinant() < 0.0 {
+            for trig in self.triangles.iter_mut() {
+                trig.vertices.swap(1, 2);
             }
         }
+    }
 
+    /// Build the mesh's BVH and get it ready to be shared between instances
+    /// The mesh can't be changed after this
+    pub fn into_shared(mut self) -> Arc<Mesh> {
+        self.build_bvh();
+        return Arc::new(self);
     }
 }
 
 impl Mesh {
 
-    /// Check if our mesh has been hit by a ray
+    /// Find the closest triangle along a ray
     /// # Arguments
     /// * 'r' - The incoming ray
     /// # Returns
-    /// * A hit struct containing the closest hit triangle and its properties
-    pub fn hit(&self, r: Ray) -> Hit {
-
-        // We want to store the closest hit triangle so we only draw those
-        let mut closest_hit = Hit::new();
-
-        // Loop through every triangle within the mesh
-        for trig in self.triangles.iter() {
-            
-            // Check if the ray has hit any of the triangles within the mesh
-            let hit: Hit = trig.hit(r);
-            if hit.t > 0.0 {
-
-                // Check if the hit triangle is closer than the current closest
-                if hit.at.z > closest_hit.at.z {
-                    closest_hit = hit;
-                    closest_hit.material = self.material.clone();
+    /// * A hit struct with only the distance, position and triangle filled in, see surface
+    pub fn intersect(&self, r: Ray) -> Hit {
+
+        // Only the triangles inside boxes the ray passes through get tested
+        return self.bvh.hit(r, |i, r| {
+            let mut hit = self.triangles[i].hit(&self.positions, r);
+            hit.triangle = i;
+            return hit;
+        });
+    }
+
+    /// Fill in the texture coordinates, color and shading frame of a hit from the triangle's vertices
+    /// Only done once the closest hit is known, rather than for every triangle tested
+    /// # Arguments
+    /// * 'hit' - Hit on one of this mesh's triangles, from intersect
+    /// # Returns
+    /// * The shading normal pointing out of the triangle and the tangent, to be passed to set_face_normal
+    pub fn surface(&self, hit: &mut Hit) -> (Vec3, Vec3) {
+        let trig = &self.triangles[hit.triangle];
+        let bary = barycentric(trig.points(&self.positions), hit.at);
+        let blend = |values: &[Vec3]| -> Vec3 {
+            let v = trig.points(values);
+            return v[0] * bary.x + v[1] * bary.y + v[2] * bary.z;
+        };
+
+        hit.uv = blend(&self.uvs);
+        hit.color = blend(&self.colors);
+        hit.geometric_normal = self.face_normal(trig);
+        hit.uv_derivatives = self.uv_derivatives(trig);
+        hit.light_sampled = true;
+
+        // If the mesh is smooth shaded, we need to calculate the interpolated normal, flat shading uses the first corner's
+        let outward_normal = if trig.smooth { unit_vector(blend(&self.normals)) } else { self.normals[trig.vertices[0] as usize] };
+
+        // Tangent follows the texture coordinates, smooth meshes blend the tangents calculated at load time
+        let blended = blend(&self.tangents);
+        let tangent = if trig.smooth && !blended.near_zero() {
+            blended
+        } else {
+            hit.uv_derivatives.map_or(Vec3::new(0.0, 0.0, 0.0), |(dpdu, _)| dpdu)
+        };
+        return (outward_normal, tangent);
+    }
+}
+
+impl Hittable for Mesh {
+
+    /// Hits are in the mesh's own space, with its own materials
+    fn hit(&self, r: Ray) -> Hit {
+        let mut closest_hit = self.intersect(r);
+        if closest_hit.t > 0.0 {
+            closest_hit.material = self.materials[self.triangles[closest_hit.triangle].material].clone();
+            let (normal, tangent) = self.surface(&mut closest_hit);
+            closest_hit.set_face_normal(r, normal, tangent);
+        }
+        return closest_hit;
+    }
+
+    /// The box is empty if the mesh has no vertices
+    fn bounds(&self) -> Option<Aabb> {
+        return Some(Aabb::from_points(&self.positions));
+    }
+}
+
+/// A corner of a triangle being added to a MeshBuilder, as read from a mesh file
+#[derive(Copy, Clone, Debug)]
+pub struct Vertex {
+    /// Identifies the vertex in the file, corners with the same id and normal share a vertex in the mesh
+    pub id: (usize, usize),
+    /// Where the corner is
+    pub position: Vec3,
+    /// Normal at the corner, if the file has one
+    pub normal: Option<Vec3>,
+    /// Texture coordinates at the corner
+    pub uv: Vec3,
+    /// Color at the corner
+    pub color: Vec3
+}
+
+impl Vertex {
+    /// Create a corner with only a position
+    /// # Arguments
+    /// * 'id' - Identifies the vertex in the file
+    /// * 'position' - Where the corner is
+    /// # Returns
+    /// * Vertex without a normal, (0,0,0) texture coordinates and white color
+    pub fn new(id: (usize, usize), position: Vec3) -> Vertex {
+        return Vertex { id, position, normal: None, uv: Vec3::new(0.0, 0.0, 0.0), color: Vec3::new(1.0, 1.0, 1.0) };
+    }
+}
+
+/// Identifies a vertex in a MeshBuilder, its id in the file and the bits of its normal
+/// Vertices whose normal is averaged from their triangles have None instead, so they're shared whatever the triangle
+type VertexKey = ((usize, usize), Option<[u64; 3]>);
+
+/// Builds a mesh out of triangles read from a file, the same way for every file format
+/// Corners that refer to the same vertex in the file only get stored once
+pub struct MeshBuilder {
+    /// The mesh so far
+    mesh: Mesh,
+    /// Index in the mesh of every vertex added so far
+    shared: HashMap<VertexKey, u32>,
+    /// Vertices whose normal gets averaged from the smooth triangles around them in build
+    generated: Vec<bool>
+}
+
+impl MeshBuilder {
+    /// Start an empty mesh, with a gray diffuse default material
+    pub fn new() -> MeshBuilder {
+        let mut mesh = Mesh::new();
+        mesh.materials = vec![MaterialEnum::Diffuse(Diffuse::new(Texture::Solid(Vec3::new(0.5, 0.5, 0.5))))];
+        return MeshBuilder { mesh, shared: HashMap::new(), generated: Vec::new() };
+    }
+
+    /// Add a triangle
+    /// If any corner is missing a normal, smooth triangles get one averaged over every triangle sharing the vertex
+    /// and flat ones get a flat normal following the winding order
+    /// # Arguments
+    /// * 'corners' - Corners of the triangle
+    /// * 'smooth' - Whether to smooth shade the triangle
+    /// * 'material' - Index into the mesh's materials
+    /// # Returns
+    /// * False if it needed a normal made up but is degenerate, it couldn't be hit anyway so it is left out
+    pub fn add_triangle(&mut self, corners: [Vertex; 3], smooth: bool, material: usize) -> bool {
+        let normals = match (corners[0].normal, corners[1].normal, corners[2].normal) {
+            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+            _ => None
+        };
+        let face_normal = cross(corners[1].position - corners[0].position, corners[2].position - corners[0].position);
+        if normals.is_none() && face_normal.near_zero() {
+            return false;
+        }
+
+        // Reuse the vertex if the file's vertex was already added with the same normal
+        let mut vertices = [0; 3];
+        for (i, corner) in corners.iter().enumerate() {
+            let (normal, key) = match (normals, smooth) {
+                (Some(n), _) => (n[i], Some([n[i].x, n[i].y, n[i].z].map(f64::to_bits))),
+                (None, true) => (Vec3::new(0.0, 0.0, 0.0), None),
+                (None, false) => {
+                    let n = unit_vector(face_normal);
+                    (n, Some([n.x, n.y, n.z].map(f64::to_bits)))
                 }
+            };
+            vertices[i] = match self.shared.get(&(corner.id, key)) {
+                Some(&v) => v,
+                None => {
+                    let v = self.mesh.add_vertex(corner.position, normal, corner.uv, corner.color);
+                    self.shared.insert((corner.id, key), v);
+                    self.generated.push(key.is_none());
+                    v
+                }
+            };
+        }
+
+        let mut trig = Triangle::new(vertices);
+        trig.smooth = smooth;
+        trig.material = material;
+        self.mesh.add(trig);
+        return true;
+    }
+
+    /// Finish the mesh, averaging the normals that weren't in the file and calculating its tangents
+    pub fn build(self) -> Mesh {
+        let mut mesh = self.mesh;
+
+        // The cross product's length is twice the triangle's area, so bigger triangles count for more
+        for trig in mesh.triangles.iter().filter(|t| t.smooth) {
+            let points = trig.points(&mesh.positions);
+            let face_normal = cross(points[1] - points[0], points[2] - points[0]);
+            for v in trig.vertices.iter().map(|&v| v as usize).filter(|&v| self.generated[v]) {
+                mesh.normals[v] = mesh.normals[v] + face_normal;
             }
         }
-        return closest_hit;
+        for (normal, _) in mesh.normals.iter_mut().zip(self.generated.iter()).filter(|(_, &generated)| generated) {
+            *normal = unit_vector(*normal);
+        }
+
+        mesh.compute_tangents();
+        return mesh;
+    }
+}
+
+/// Parse the numbers following an OBJ statement
+/// # Arguments
+/// * 'words' - The statement's words, keyword first
+/// * 'count' - How many numbers are needed, any extra are ignored
+/// # Returns
+/// * The numbers, or a message saying what's wrong with them
+fn parse_numbers(words: &[&str], count: usize) -> Result<Vec<f64>, String> {
+    if words.len() <= count {
+        return Err(format!("{} needs {} numbers", words[0], count));
+    }
+    return words[1..=count].iter()
+        .map(|w| w.parse::<f64>().map_err(|_| format!("'{}' is not a number", w)))
+        .collect();
+}
+
+/// Turn an OBJ index into a position in a list
+/// Indices count from 1, negative ones count back from the end of what's been read so far
+/// # Arguments
+/// * 'text' - The index as written in the file
+/// * 'len' - How many elements have been read so far
+/// * 'name' - What is being indexed, used in the error message
+fn parse_index(text: &str, len: usize, name: &str) -> Result<usize, String> {
+    let index: i64 = text.parse().map_err(|_| format!("'{}' is not a valid {} index", text, name))?;
+    let resolved = if index < 0 { len as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(format!("{} index {} is out of range, only {} defined so far", name, index, len));
+    }
+    return Ok(resolved as usize);
+}
+
+/// A single corner of a face
+/// Texture coordinates and normals are optional, "v", "v/vt", "v//vn" and "v/vt/vn" are all valid
+struct Corner {
+    point: usize,
+    uv: Option<usize>,
+    normal: Option<usize>
+}
+
+/// Parse a face corner
+/// # Arguments
+/// * 'text' - The corner as written in the file
+/// * 'counts' - How many vertices, texture coordinates and normals have been read so far
+fn parse_corner(text: &str, counts: (usize, usize, usize)) -> Result<Corner, String> {
+    let parts: Vec<&str> = text.split('/').collect();
+    if parts.len() > 3 || parts[0].is_empty() {
+        return Err(format!("'{}' is not a valid face vertex", text));
     }
+
+    // Empty parts are left out, like the texture coordinate in "v//vn"
+    let optional = |i: usize, len: usize, name: &str| -> Result<Option<usize>, String> {
+        match parts.get(i).filter(|p| !p.is_empty()) {
+            Some(p) => return parse_index(p, len, name).map(Some),
+            None => return Ok(None)
+        }
+    };
+    return Ok(Corner {
+        point: parse_index(parts[0], counts.0, "vertex")?,
+        uv: optional(1, counts.1, "texture coordinate")?,
+        normal: optional(2, counts.2, "normal")?
+    });
 }
 
 /// Load an OBJ mesh
+/// Faces with more than 3 vertices are split into a fan of triangles around their first vertex
 /// # Arguments
 /// * 'path' - Path of an OBJ file
-/// * 'smooth' - Boolean which states if the mesh is smooth shaded
+/// * 'smooth' - Boolean which states if the mesh is smooth shaded, faces without normals get them averaged from their neighbours
 /// # Returns
-/// * A mesh and all of its triangles, including a default material
-pub fn load_mesh(path: &str, smooth: bool) -> Mesh {
-    let file = File::open(path).expect("Failed to open file");
-    let reader = BufReader::new(file);
+/// * A mesh and all of its triangles, with a default material followed by any from its material libraries
+/// * Or a message pointing at the line of the file that couldn't be read
+pub fn load_mesh(path: &str, smooth: bool) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+    return parse_obj(&String::from_utf8_lossy(&bytes), path, smooth);
+}
 
-    // Will store all vertices, normals, and triangles
-    let mut vertices: Vec<[f64; 3]> = Vec::new();
-    let mut normals: Vec<[f64; 3]> = Vec::new();
-    let mut triangles: Vec<Triangle> = Vec::new();
+/// Read an OBJ mesh from the contents of a file, see load_mesh
+/// # Arguments
+/// * 'text' - The whole file
+/// * 'path' - Path of the file, used in error messages and to find material libraries
+/// * 'smooth' - Whether the mesh is smooth shaded
+fn parse_obj(text: &str, path: &str, smooth: bool) -> Result<Mesh, String> {
+
+    // Will store all vertices, normals, and texture coordinates, the mesh shares them between its triangles
+    let mut vertices: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec3> = Vec::new();
+    let mut mesh = MeshBuilder::new();
+
+    // Materials from the OBJ's material libraries, after the default one at index 0
+    let mut materials: Vec<MaterialEnum> = Vec::new();
+    let mut material_names: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
 
     // For each line in the obj file
-    for line in reader.lines() {
-        let line = line.expect("Failed to read line");
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}:{}: {}", path, number + 1, message);
 
-        // Split by white space
+        // Comments can follow a statement on the same line, then split by white space
+        let line = line.split('#').next().unwrap_or("");
         let words: Vec<&str> = line.split_whitespace().collect();
 
-        if words.len() == 0 {
+        if words.is_empty() {
             continue;
         }
 
         // If the first word is v, this means vertex
         if words[0] == "v" {
 
-            // Push the vertex into the vertices vec
-            vertices.push([
-                words[1].parse().unwrap(),
-                words[2].parse().unwrap(),
-                words[3].parse().unwrap()
-            ]);
+            // Push the vertex into the vertices vec, an optional w is ignored
+            let v = parse_numbers(&words, 3).map_err(error)?;
+            vertices.push(Vec3::new(v[0], v[1], v[2]));
         
         // If it's a vertex normal
         } else if words[0] == "vn" {
-            normals.push([
-                words[1].parse().unwrap(),
-                words[2].parse().unwrap(),
-                words[3].parse().unwrap()
-            ]);
+            let n = parse_numbers(&words, 3).map_err(error)?;
+            normals.push(Vec3::new(n[0], n[1], n[2]));
+
+        // If it's a texture coordinate, v defaults to 0 and the optional w is ignored
+        } else if words[0] == "vt" {
+            let u = parse_numbers(&words, 1).map_err(error)?;
+            let v = if words.len() > 2 { parse_numbers(&words, 2).map_err(error)?[1] } else { 0.0 };
+            uvs.push(Vec3::new(u[0], v, 0.0));
+
+        // Material libraries are relative to the OBJ file, a broken one only loses its materials
+        } else if words[0] == "mtllib" {
+            let library = Path::new(path).parent().unwrap_or(Path::new("")).join(words[1..].join(" "));
+            match load_mtl(&library) {
+                Ok(library) => {
+                    for (name, material) in library {
+                        materials.push(material);
+                        material_names.insert(name, materials.len());
+                    }
+                },
+                Err(e) => eprintln!("Warning: {}", e)
+            }
+
+        // Every face after this uses the named material
+        } else if words[0] == "usemtl" {
+            let name = words[1..].join(" ");
+            current_material = material_names.get(&name).copied().unwrap_or_else(|| {
+                eprintln!("Warning: {}:{}: unknown material '{}'", path, number + 1, name);
+                return 0;
+            });
 
         // If it's a face
         } else if words[0] == "f" {
-
-            // Split by '/'
-            let v1: Vec<&str> = words[1].split('/').collect();
-            let v2: Vec<&str> = words[2].split('/').collect();
-            let v3: Vec<&str> = words[3].split('/').collect();
-
-            // Match the points and the normals
-            let p1: usize = v1[0].parse().unwrap();
-            let n1: usize = v1[2].parse().unwrap();
-
-            let p2: usize = v2[0].parse().unwrap();
-            let n2: usize = v2[2].parse().unwrap();
-
-            let p3: usize = v3[0].parse().unwrap();
-            let n3: usize = v3[2].parse().unwrap();
-
-            // Create a new triangle 
-            let mut trig = Triangle::new(
-                Vec3::new(vertices[p1-1][0], vertices[p1-1][1], vertices[p1-1][2],),
-                Vec3::new(vertices[p2-1][0], vertices[p2-1][1], vertices[p2-1][2],),
-                Vec3::new(vertices[p3-1][0], vertices[p3-1][1], vertices[p3-1][2],),
-                Vec3::new(normals[n1-1][0], normals[n1-1][1], normals[n1-1][2]),
-            );
-
-            // If it's a smoothly shaded mesh, add to the triangle normals
-            if smooth {
-                trig.smooth = true;
-
-                trig.normals = [
-                    Vec3::new(normals[n1-1][0], normals[n1-1][1], normals[n1-1][2]),
-                    Vec3::new(normals[n2-1][0], normals[n2-1][1], normals[n2-1][2]),
-                    Vec3::new(normals[n3-1][0], normals[n3-1][1], normals[n3-1][2]),
-                ];
+            if words.len() < 4 {
+                return Err(error("a face needs at least 3 vertices".to_string()));
+            }
+            let counts = (vertices.len(), uvs.len(), normals.len());
+            let corners: Vec<Corner> = words[1..].iter().map(|w| parse_corner(w, counts)).collect::<Result<_, _>>().map_err(error)?;
+
+            // Split the face into a fan of triangles around its first corner
+            for i in 1..corners.len() - 1 {
+                let fan = [&corners[0], &corners[i], &corners[i + 1]].map(|c| {
+
+                    // The same position with different texture coordinates is a different vertex
+                    let mut vertex = Vertex::new((c.point, c.uv.map_or(0, |t| t + 1)), vertices[c.point]);
+                    vertex.normal = c.normal.map(|n| normals[n]);
+
+                    // Texture coordinates are optional, "v//vn" leaves them out
+                    if let Some(t) = c.uv {
+                        vertex.uv = uvs[t];
+                    }
+                    return vertex;
+                });
+
+                // Corners without a normal get one made up, see MeshBuilder::add_triangle
+                mesh.add_triangle(fan, smooth, current_material);
             }
-
-            // Push the triangle to the vec
-            triangles.push(trig);
         }
     }
 
     // Return the new mesh based on the triangles
-    return Mesh::new_mesh(triangles);
-}
\ No newline at end of file
+    let mut mesh = mesh.build();
+    mesh.materials.extend(materials);
+    return Ok(mesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Mesh, String> {
+        return parse_obj(text, "test.obj", true);
+    }
+
+    /// A unit square in the xy plane
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn faces_are_split_into_a_fan() {
+        let mesh = parse(&format!("{}v 0.5 1.5 0\nf 1 2 3 5 4\n", SQUARE)).unwrap();
+        assert_eq!(mesh.triangles.len(), 3);
+        let points: Vec<[Vec3; 3]> = mesh.triangles.iter().map(|t| t.points(&mesh.positions)).collect();
+        for (trig, corners) in points.iter().zip([[1.0, 1.0], [1.0, 0.5], [0.5, 0.0]]) {
+            assert_eq!(trig[0].x, 0.0);
+            assert_eq!((trig[1].x, trig[2].x), (corners[0], corners[1]));
+        }
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_read() {
+        let relative = parse(&format!("{}vt 0 0\nvt 1 0\nvt 1 1\nf -4/-3 -3/-2 -2/-1\n", SQUARE)).unwrap();
+        let absolute = parse(&format!("{}vt 0 0\nvt 1 0\nvt 1 1\nf 1/1 2/2 3/3\n", SQUARE)).unwrap();
+        assert_eq!(relative.positions.len(), 3);
+        for (a, b) in relative.positions.iter().zip(absolute.positions.iter()).chain(relative.uvs.iter().zip(absolute.uvs.iter())) {
+            assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+        }
+    }
+
+    #[test]
+    fn missing_texture_coordinates_and_normals() {
+        let mesh = parse(&format!("{}vn 0 0 1\nf 1//1 2//1 3//1\nf 1 3 4\n", SQUARE)).unwrap();
+        assert_eq!(mesh.triangles.len(), 2);
+        assert!(mesh.uvs.iter().all(|uv| uv.near_zero()));
+
+        // Both end up facing +z, the second's normals averaged from its own winding
+        assert!(mesh.normals.iter().all(|n| (n.z - 1.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn comments_after_a_statement_are_ignored() {
+        let mesh = parse(&format!("# a square\n{}f 1 2 3 # first half\nf 1 3 4#second half\n", SQUARE)).unwrap();
+        assert_eq!(mesh.triangles.len(), 2);
+    }
+
+    #[test]
+    fn mirrored_mesh_is_hit_from_the_front() {
+        let mut mesh = parse(&format!("{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\n", SQUARE)).unwrap();
+        mesh.transform(&Transform::scale(Vec3::new(-1.0, 1.0, 1.0)));
+        let instance = crate::instance::Instance::new(mesh.into_shared(), Transform::identity()).unwrap();
+
+        // The normal still points at +z, so a ray coming down from there hits the outside
+        let hit = crate::hittable::Hittable::hit(&instance, Ray::new(Vec3::new(-0.7, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0)));
+        assert!(hit.t > 0.0);
+        assert!(hit.front_face);
+        assert!(hit.geometric_normal.z > 0.99 && hit.normal.z > 0.99);
+
+        // u now runs towards -x, and the frame follows it rather than staying right handed
+        let (dpdu, dpdv) = hit.uv_derivatives.unwrap();
+        assert!(crate::vec3::dot(hit.tangent, dpdu) > 0.0 && crate::vec3::dot(hit.bitangent, dpdv) > 0.0);
+    }
+
+    #[test]
+    fn bad_index_names_the_line() {
+        let error = parse(&format!("{}f 1 2 5\n", SQUARE)).unwrap_err();
+        assert_eq!(error, "test.obj:5: vertex index 5 is out of range, only 4 defined so far");
+        let error = parse(&format!("{}f 0 1 2\n", SQUARE)).unwrap_err();
+        assert_eq!(error, "test.obj:5: vertex index 0 is out of range, only 4 defined so far");
+        let error = parse(&format!("{}f 1 2 three\n", SQUARE)).unwrap_err();
+        assert_eq!(error, "test.obj:5: 'three' is not a valid vertex index");
+    }
+}
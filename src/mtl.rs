@@ -0,0 +1,284 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use crate::{vec3::Vec3, material::{MaterialEnum, Dielectric, Emissive, Principled}, texture::{Texture, ImageTexture, NormalMap, WrapMode}};
+
+/// How far a bump map height of 1.0 raises the surface, MTL files don't say so it's picked to look about right
+const BUMP_SCALE: f64 = 0.02;
+
+/// Everything an MTL file can say about a material that we make use of
+struct MtlMaterial {
+    name: String,
+    /// Diffuse color
+    kd: Vec3,
+    /// Specular color
+    ks: Vec3,
+    /// Specular exponent, 0 to 1000
+    ns: f64,
+    /// Index of refraction
+    ni: f64,
+    /// Opacity, 1.0 is opaque
+    d: f64,
+    /// Emitted color
+    ke: Vec3,
+    /// Physically based extension, roughness and metallic
+    pr: Option<f64>,
+    pm: Option<f64>,
+    /// Diffuse texture and bump map, with their options
+    map_kd: Option<MapStatement>,
+    map_bump: Option<MapStatement>
+}
+
+/// A map_ statement, a file name after any options
+struct MapStatement {
+    path: String,
+    wrap: WrapMode,
+    /// Bump multiplier from -bm
+    bump: f64
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> MtlMaterial {
+        return MtlMaterial {
+            name: name.to_string(),
+            kd: Vec3::new(0.8, 0.8, 0.8),
+            ks: Vec3::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            ke: Vec3::new(0.0, 0.0, 0.0),
+            pr: None,
+            pm: None,
+            map_kd: None,
+            map_bump: None
+        };
+    }
+}
+
+/// Parse the numbers of a statement into a color
+/// A single number is a gray
+fn parse_color(words: &[&str]) -> Result<Vec3, String> {
+    let values: Vec<f64> = words.iter().map(|w| w.parse::<f64>()).collect::<Result<_, _>>()
+        .map_err(|_| format!("'{}' is not a color", words.join(" ")))?;
+    match values.len() {
+        1 => return Ok(Vec3::new(values[0], values[0], values[0])),
+        3 => return Ok(Vec3::new(values[0], values[1], values[2])),
+        _ => return Err(format!("'{}' is not a color", words.join(" ")))
+    }
+}
+
+/// Parse a single number
+fn parse_value(words: &[&str]) -> Result<f64, String> {
+    return words.first().and_then(|w| w.parse().ok()).ok_or(format!("'{}' is not a number", words.join(" ")));
+}
+
+/// Parse a map_ statement's options and file name
+/// # Arguments
+/// * 'words' - Everything after the statement's keyword
+fn parse_map(words: &[&str]) -> Result<MapStatement, String> {
+    let mut map = MapStatement { path: String::new(), wrap: WrapMode::Repeat, bump: 1.0 };
+    let mut i = 0;
+    while i < words.len() && words[i].starts_with('-') {
+        let option = words[i];
+        i += 1;
+
+        // Options take a varying number of arguments, eat everything up to the next option or the file name
+        let start = i;
+        while i < words.len() && (words[i].parse::<f64>().is_ok() || words[i] == "on" || words[i] == "off") {
+            i += 1;
+        }
+        let arguments = &words[start..i];
+        match option {
+            "-clamp" if arguments.first() == Some(&"on") => map.wrap = WrapMode::Clamp,
+            "-bm" => map.bump = parse_value(arguments)?,
+            _ => {}
+        }
+    }
+
+    // File names can have spaces in them
+    map.path = words[i..].join(" ");
+    if map.path.is_empty() {
+        return Err(format!("'{}' is missing a file name", words.join(" ")));
+    }
+    return Ok(map);
+}
+
+/// Read a single statement into the material it belongs to
+/// # Arguments
+/// * 'material' - The material being defined
+/// * 'words' - The statement's words, keyword first
+/// # Returns
+/// * A message saying what's wrong with the statement, the material is left as it was
+fn parse_statement(material: &mut MtlMaterial, words: &[&str]) -> Result<(), String> {
+    let arguments = &words[1..];
+    match words[0] {
+        "Kd" => material.kd = parse_color(arguments)?,
+        "Ks" => material.ks = parse_color(arguments)?,
+        "Ke" => material.ke = parse_color(arguments)?,
+        "Ns" => material.ns = parse_value(arguments)?,
+        "Ni" => material.ni = parse_value(arguments)?,
+        "d" => material.d = parse_value(arguments)?,
+
+        // Some exporters write transparency instead of opacity
+        "Tr" => material.d = 1.0 - parse_value(arguments)?,
+        "Pr" => material.pr = Some(parse_value(arguments)?),
+        "Pm" => material.pm = Some(parse_value(arguments)?),
+        "map_Kd" => material.map_kd = Some(parse_map(arguments)?),
+        "map_Bump" | "map_bump" | "bump" => material.map_bump = Some(parse_map(arguments)?),
+
+        // Everything else, like illum and the other maps, has no equivalent here
+        _ => {}
+    }
+    return Ok(());
+}
+
+/// Load an MTL material library
+/// Statements that can't be read and textures that can't be loaded are skipped with a warning, the rest of the file still counts
+/// # Arguments
+/// * 'path' - Path of the MTL file, texture paths are relative to it
+/// # Returns
+/// * Every material in the file with its name, or a message if the file can't be read at all
+pub fn load_mtl(path: &Path) -> Result<Vec<(String, MaterialEnum)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    return Ok(parse_mtl(&text, path));
+}
+
+/// Read an MTL material library from the contents of a file, see load_mtl
+/// # Arguments
+/// * 'text' - The whole file
+/// * 'path' - Path of the file, used in warnings and to find textures
+fn parse_mtl(text: &str, path: &Path) -> Vec<(String, MaterialEnum)> {
+
+    let mut parsed: Vec<MtlMaterial> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() || words[0].starts_with('#') {
+            continue;
+        }
+
+        if words[0] == "newmtl" {
+            parsed.push(MtlMaterial::new(&words[1..].join(" ")));
+            continue;
+        }
+        let Some(material) = parsed.last_mut() else {
+            eprintln!("Warning: {}:{}: skipping {} before any newmtl", path.display(), number + 1, words[0]);
+            continue;
+        };
+        if let Err(e) = parse_statement(material, &words) {
+            eprintln!("Warning: {}:{}: skipping {}, {}", path.display(), number + 1, words[0], e);
+        }
+    }
+
+    // The same image is often used by several materials, only load it once
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut images: HashMap<(String, bool), Arc<ImageTexture>> = HashMap::new();
+    let mut load_image = |map: &MapStatement, color: bool| -> Option<Arc<ImageTexture>> {
+        let key = (map.path.clone(), color);
+        if let Some(image) = images.get(&key) {
+            return Some(image.clone());
+        }
+        let file = base.join(&map.path).to_string_lossy().to_string();
+        let image = if color { ImageTexture::load(&file, map.wrap) } else { ImageTexture::load_linear(&file, map.wrap) };
+        match image {
+            Ok(image) => {
+                let image = Arc::new(image);
+                images.insert(key, image.clone());
+                return Some(image);
+            },
+            Err(e) => {
+                eprintln!("Warning: {}: {}", path.display(), e);
+                return None;
+            }
+        }
+    };
+
+    let mut materials = Vec::new();
+    for m in parsed.iter() {
+
+        // Lights and glass have no use for textures, say so rather than quietly losing them
+        let untextured = |kind: &str| {
+            for (statement, map) in [("map_Kd", &m.map_kd), ("map_Bump", &m.map_bump)] {
+                if let Some(map) = map {
+                    eprintln!("Warning: {}: material '{}' is {}, ignoring its {} {}", path.display(), m.name, kind, statement, map.path);
+                }
+            }
+        };
+
+        let material = if !m.ke.near_zero() {
+
+            // Anything glowing is a light
+            untextured("emissive");
+            MaterialEnum::Emissive(Emissive::new(m.ke, 1.0))
+        } else if m.d < 1.0 {
+
+            // See through materials are taken to be glass
+            untextured("glass");
+            MaterialEnum::Dielectric(Dielectric::new(Vec3::new(1.0, 1.0, 1.0), if m.ni > 1.0 { m.ni } else { 1.5 }))
+        } else {
+            let base_color = match m.map_kd.as_ref().and_then(|map| load_image(map, true)) {
+                Some(image) => Texture::Image(image),
+                None => Texture::Solid(m.kd)
+            };
+
+            // Blinn-Phong exponent to roughness, the usual conversion through Beckmann's alpha
+            let roughness = m.pr.unwrap_or_else(|| (2.0 / (m.ns.max(0.0) + 2.0)).sqrt().sqrt());
+
+            // Ks is a Phong highlight color, which says nothing about how much a dielectric reflects
+            // so anything with a highlight gets the usual 4% of plastic, and only a black Ks turns reflection off
+            let specular = if m.ks.near_zero() { 0.0 } else { 0.5 };
+
+            let metallic = m.pm.unwrap_or(0.0);
+            let mut principled = Principled::new(base_color, Texture::Solid(Vec3::new(metallic, metallic, metallic)),
+                Texture::Solid(Vec3::new(roughness, roughness, roughness)), specular);
+            if let Some((map, image)) = m.map_bump.as_ref().and_then(|map| Some((map, load_image(map, false)?))) {
+                principled.normal_map = Some(NormalMap::Bump {
+                    height: Texture::Image(image),
+                    strength: map.bump * BUMP_SCALE
+                });
+            }
+            MaterialEnum::Principled(principled)
+        };
+        materials.push((m.name.clone(), material));
+    }
+    return materials;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<(String, MaterialEnum)> {
+        return parse_mtl(text, Path::new("test.mtl"));
+    }
+
+    #[test]
+    fn bad_statements_only_lose_themselves() {
+        let materials = parse("Kd 1 0 0\nnewmtl red\nKd 1 0 0\nNs lots\nKd 0.5 0.5\nnewmtl glass\nd 0.5\nNi 1.33\n");
+        assert_eq!(materials.len(), 2);
+        let MaterialEnum::Principled(red) = &materials[0].1 else {
+            panic!("red should be principled");
+        };
+        let Texture::Solid(color) = red.base_color else {
+            panic!("red has no texture");
+        };
+        assert_eq!((color.x, color.y, color.z), (1.0, 0.0, 0.0));
+        assert!(matches!(materials[1].1, MaterialEnum::Dielectric(_)));
+    }
+
+    #[test]
+    fn ks_only_turns_reflection_on_or_off() {
+        let materials = parse("newmtl plastic\nKs 0.9 0.9 0.9\nnewmtl dull\nKs 0 0 0\nnewmtl unset\n");
+        let specular: Vec<f64> = materials.iter().map(|(_, m)| match m {
+            MaterialEnum::Principled(p) => p.specular,
+            _ => panic!("should be principled")
+        }).collect();
+        assert_eq!(specular, vec![0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_texture_falls_back_to_kd() {
+        let materials = parse("newmtl tiles\nKd 0.2 0.4 0.6\nmap_Kd -clamp on does_not_exist.png\n");
+        let MaterialEnum::Principled(tiles) = &materials[0].1 else {
+            panic!("tiles should be principled");
+        };
+        assert!(matches!(tiles.base_color, Texture::Solid(_)));
+    }
+}
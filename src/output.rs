@@ -0,0 +1,239 @@
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+use image::{Rgb, codecs::hdr::HdrEncoder};
+use crate::{vec3::Vec3, render::DrawingMode, framebuffer::Framebuffer};
+
+/// File formats an image can be saved as
+#[derive(Copy, Clone, Debug)]
+pub enum ImageFormat {
+    /// Binary P6 PPM
+    Ppm,
+    Png,
+    /// OpenEXR, uncompressed 32 bit float linear light
+    Exr,
+    /// Radiance RGBE, linear light
+    Hdr
+}
+
+impl ImageFormat {
+
+    /// Pick the format based on a file's extension
+    /// # Arguments
+    /// * 'path' - Path of the output file
+    /// # Returns
+    /// * The format, or a message listing the supported extensions
+    pub fn from_path(path: &str) -> Result<ImageFormat, String> {
+        let extension = Path::new(path).extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "ppm" => return Ok(ImageFormat::Ppm),
+            "png" => return Ok(ImageFormat::Png),
+            "exr" => return Ok(ImageFormat::Exr),
+            "hdr" => return Ok(ImageFormat::Hdr),
+            _ => return Err(format!("unsupported output format '{}', expected .png, .ppm, .exr or .hdr", path))
+        }
+    }
+}
+
+/// 8 bit RGB image, stored row by row from the top left
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// r,g,b values of every pixel
+    pub pixels: Vec<[u8; 3]>
+}
+
+impl Image {
+
+    /// Create a new black image
+    /// # Arguments
+    /// * 'width, height' - Size in pixels
+    pub fn new(width: u32, height: u32) -> Image {
+        return Image { width, height, pixels: vec![[0, 0, 0]; (width * height) as usize] };
+    }
+
+    /// Set the color of a single pixel
+    /// # Arguments
+    /// * 'x, y' - Pixel position, (0,0) is the top left
+    /// * 'rgb' - New color
+    pub fn set(&mut self, x: u32, y: u32, rgb: [u8; 3]) {
+        self.pixels[(y * self.width + x) as usize] = rgb;
+    }
+
+    /// Save the image, the format is picked from the file extension
+    /// # Arguments
+    /// * 'path' - File to write
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        match ImageFormat::from_path(path)? {
+            ImageFormat::Ppm => return self.write_ppm(path).map_err(|e| format!("failed to write {}: {}", path, e)),
+            ImageFormat::Png => {
+                return image::save_buffer(path, self.pixels.as_flattened(), self.width, self.height, image::ColorType::Rgb8)
+                    .map_err(|e| format!("failed to write {}: {}", path, e));
+            },
+            ImageFormat::Exr | ImageFormat::Hdr => return Err(format!("{} needs a framebuffer, not an 8 bit image", path))
+        }
+    }
+
+    /// Write the image as a binary P6 PPM
+    /// # Arguments
+    /// * 'path' - File to write
+    fn write_ppm(&self, path: &str) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(format!("P6\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        file.write_all(self.pixels.as_flattened())?;
+        return file.flush();
+    }
+}
+
+/// Save a framebuffer, the format is picked from the file extension
+/// HDR formats get the linear colors as they are, everything else is tonemapped down to 8 bits first
+/// # Arguments
+/// * 'fb' - Framebuffer to save
+/// * 'mode' - Drawing mode the framebuffer was rendered with
+/// * 'path' - File to write
+pub fn save_framebuffer(fb: &Framebuffer, mode: DrawingMode, path: &str) -> Result<(), String> {
+    let result = match ImageFormat::from_path(path)? {
+        ImageFormat::Exr => write_exr(fb, path),
+        ImageFormat::Hdr => write_hdr(fb, path),
+        _ => return tonemap(fb, mode).save(path)
+    };
+    return result.map_err(|e| format!("failed to write {}: {}", path, e));
+}
+
+/// Tonemap a framebuffer down to an 8 bit image
+/// # Arguments
+/// * 'fb' - Framebuffer holding linear colors
+/// * 'mode' - Drawing mode the framebuffer was rendered with
+pub fn tonemap(fb: &Framebuffer, mode: DrawingMode) -> Image {
+    let mut image = Image::new(fb.width, fb.height);
+    for y in 0..fb.height {
+        for x in 0..fb.width {
+            image.set(x, y, to_rgb8(fb.get(x, y), mode));
+        }
+    }
+    return image;
+}
+
+/// Write a framebuffer as a Radiance RGBE .hdr file
+/// # Arguments
+/// * 'fb' - Framebuffer to write
+/// * 'path' - File to write
+fn write_hdr(fb: &Framebuffer, path: &str) -> std::io::Result<()> {
+    let data: Vec<Rgb<f32>> = fb.pixels.iter().map(|c| Rgb([c.x as f32, c.y as f32, c.z as f32])).collect();
+    let file = BufWriter::new(File::create(path)?);
+    return HdrEncoder::new(file).encode(&data, fb.width as usize, fb.height as usize)
+        .map_err(std::io::Error::other);
+}
+
+/// Write a single OpenEXR header attribute
+/// # Arguments
+/// * 'out' - Header being written
+/// * 'name, kind' - Attribute name and type
+/// * 'value' - Attribute value
+fn exr_attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Write a framebuffer as a single part, uncompressed, scanline OpenEXR file with 32 bit float R,G,B channels
+/// # Arguments
+/// * 'fb' - Framebuffer to write
+/// * 'path' - File to write
+/// # Credit
+/// * Layout follows the "OpenEXR File Layout" document, <https://openexr.com/en/latest/OpenEXRFileLayout.html>
+fn write_exr(fb: &Framebuffer, path: &str) -> std::io::Result<()> {
+
+    // Magic number, then version 2 with no flags set
+    let mut out: Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+    // Channels have to be listed in alphabetical order
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes()); // FLOAT pixels
+        channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+        channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    channels.push(0);
+
+    let mut window = Vec::new();
+    for v in [0, 0, fb.width as i32 - 1, fb.height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+
+    exr_attribute(&mut out, "channels", "chlist", &channels);
+    exr_attribute(&mut out, "compression", "compression", &[0]);
+    exr_attribute(&mut out, "dataWindow", "box2i", &window);
+    exr_attribute(&mut out, "displayWindow", "box2i", &window);
+    exr_attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(&mut out, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    exr_attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(&mut out, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    out.push(0);
+
+    // Uncompressed files store one scanline per chunk, the offset table points at each of them
+    let line_size = fb.width as usize * 3 * 4;
+    let table_end = out.len() + fb.height as usize * 8;
+    for y in 0..fb.height as usize {
+        let offset = (table_end + y * (8 + line_size)) as u64;
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    // Each chunk is the line number, the data size, then the whole line of each channel in turn
+    for y in 0..fb.height {
+        out.extend_from_slice(&(y as i32).to_le_bytes());
+        out.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for channel in 0..3 {
+            for x in 0..fb.width {
+                let c = fb.get(x, y);
+                let v = match channel {
+                    0 => c.z,
+                    1 => c.y,
+                    _ => c.x
+                };
+                out.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+        }
+    }
+
+    let mut file = File::create(path)?;
+    return file.write_all(&out);
+}
+
+/// Convert a rendered color to an 8 bit pixel
+/// # Arguments
+/// * 'color' - Linear color which we wish to convert
+/// * 'mode' - Drawing mode
+/// # Returns
+/// * r,g,b values in the range 0-255
+pub fn to_rgb8(color: Vec3, mode: DrawingMode) -> [u8; 3] {
+    let r: u32;
+    let g: u32;
+    let b: u32;
+    match mode {
+        DrawingMode::Colors | DrawingMode::Normals => {
+
+            // If we're drawing colors/normals, simply multiply by 255
+            // Input color is 0-1, so multiply by 255 to make it in a range of 0-255
+            r = (color.x.clamp(0.0, 1.0) * 255.0) as u32;
+            g = (color.y.clamp(0.0, 1.0) * 255.0) as u32;
+            b = (color.z.clamp(0.0, 1.0) * 255.0) as u32;
+        },
+        DrawingMode::Samples => {
+
+            // Perform gamma correction, anything brighter than white gets clipped
+            r = (color.x.sqrt().clamp(0.0, 0.999) * 255.0) as u32;
+            g = (color.y.sqrt().clamp(0.0, 0.999) * 255.0) as u32;
+            b = (color.z.sqrt().clamp(0.0, 0.999) * 255.0) as u32;
+
+        }
+    }
+    return [r as u8, g as u8, b as u8];
+}
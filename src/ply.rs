@@ -0,0 +1,457 @@
+use std::fs;
+use crate::{vec3::{Vec3, unit_vector}, mesh::{Mesh, MeshBuilder, Vertex}, material::{MaterialEnum, Diffuse}, texture::{Texture, srgb_to_linear}};
+
+/// How the body of a PLY file is stored
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+/// Type a property value is stored as
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Scalar {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double
+}
+
+impl Scalar {
+
+    /// Look up a type by the name the header gives it, both the old and the sized names are allowed
+    fn parse(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => return Some(Scalar::Char),
+            "uchar" | "uint8" => return Some(Scalar::UChar),
+            "short" | "int16" => return Some(Scalar::Short),
+            "ushort" | "uint16" => return Some(Scalar::UShort),
+            "int" | "int32" => return Some(Scalar::Int),
+            "uint" | "uint32" => return Some(Scalar::UInt),
+            "float" | "float32" => return Some(Scalar::Float),
+            "double" | "float64" => return Some(Scalar::Double),
+            _ => return None
+        }
+    }
+
+    /// Size in bytes in a binary file
+    fn size(&self) -> usize {
+        match self {
+            Scalar::Char | Scalar::UChar => return 1,
+            Scalar::Short | Scalar::UShort => return 2,
+            Scalar::Int | Scalar::UInt | Scalar::Float => return 4,
+            Scalar::Double => return 8
+        }
+    }
+
+    /// Whether the type holds fractions, colors stored this way are 0 to 1 rather than 0 to 255
+    fn is_float(&self) -> bool {
+        return matches!(self, Scalar::Float | Scalar::Double);
+    }
+
+    /// Decode a value from a binary file
+    /// # Arguments
+    /// * 'b' - Exactly size() bytes
+    /// * 'big_endian' - Byte order of the file
+    fn decode(&self, b: &[u8], big_endian: bool) -> f64 {
+        // Put the bytes in little endian order so there's only one way to decode each type
+        let mut le = [0u8; 8];
+        le[..b.len()].copy_from_slice(b);
+        if big_endian {
+            le[..b.len()].reverse();
+        }
+        match self {
+            Scalar::Char => return le[0] as i8 as f64,
+            Scalar::UChar => return le[0] as f64,
+            Scalar::Short => return i16::from_le_bytes([le[0], le[1]]) as f64,
+            Scalar::UShort => return u16::from_le_bytes([le[0], le[1]]) as f64,
+            Scalar::Int => return i32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+            Scalar::UInt => return u32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+            Scalar::Float => return f32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+            Scalar::Double => return f64::from_le_bytes(le)
+        }
+    }
+}
+
+/// A property of an element, either a single value or a list of them with a count in front
+#[derive(Clone, Debug)]
+enum Property {
+    Value { name: String, kind: Scalar },
+    List { name: String, count: Scalar, item: Scalar }
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Value { name, .. } | Property::List { name, .. } => return name
+        }
+    }
+}
+
+/// An element declared in the header, like vertex or face, and how many of them follow
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+impl Element {
+    /// Position of a property within each of the element's entries
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        return self.properties.iter().position(|p| names.contains(&p.name()));
+    }
+}
+
+/// Reads the entries following the header, one element entry at a time
+enum Body<'a> {
+    /// One entry per line, line numbers carry on from the header's so they match the file
+    Ascii { lines: std::iter::Enumerate<std::str::Lines<'a>>, first_line: usize },
+    Binary { bytes: &'a [u8], position: usize, big_endian: bool }
+}
+
+impl<'a> Body<'a> {
+
+    /// Read the next entry of an element
+    /// # Arguments
+    /// * 'element' - The element being read
+    /// * 'path' - Path of the file, used in error messages
+    /// # Returns
+    /// * The values of each of the element's properties, a single one unless it's a list
+    fn read(&mut self, element: &Element, path: &str) -> Result<Vec<Vec<f64>>, String> {
+        match self {
+            Body::Ascii { lines, first_line } => {
+                // Blank lines don't count as entries
+                let (number, line) = lines.find(|(_, l)| !l.trim().is_empty())
+                    .ok_or(format!("{}: file ends in the middle of the {} entries", path, element.name))?;
+                let error = |message: String| format!("{}:{}: {}", path, *first_line + number + 1, message);
+
+                let mut words = line.split_whitespace();
+                let mut next = || -> Result<f64, String> {
+                    let word = words.next().ok_or(format!("{} entry is missing values", element.name))?;
+                    return word.parse().map_err(|_| format!("'{}' is not a number", word));
+                };
+                let mut values = Vec::with_capacity(element.properties.len());
+                for property in element.properties.iter() {
+                    match property {
+                        Property::Value { .. } => values.push(vec![next().map_err(error)?]),
+                        Property::List { .. } => {
+                            let count = next().map_err(error)? as usize;
+                            values.push((0..count).map(|_| next()).collect::<Result<_, _>>().map_err(error)?);
+                        }
+                    }
+                }
+                return Ok(values);
+            },
+            Body::Binary { bytes, position, big_endian } => {
+                let mut next = |kind: Scalar| -> Result<f64, String> {
+                    let b = bytes.get(*position..*position + kind.size())
+                        .ok_or(format!("{}: file ends in the middle of the {} entries", path, element.name))?;
+                    *position += kind.size();
+                    return Ok(kind.decode(b, *big_endian));
+                };
+                let mut values = Vec::with_capacity(element.properties.len());
+                for property in element.properties.iter() {
+                    match property {
+                        Property::Value { kind, .. } => values.push(vec![next(*kind)?]),
+                        Property::List { count, item, .. } => {
+                            let count = next(*count)? as usize;
+                            values.push((0..count).map(|_| next(*item)).collect::<Result<_, _>>()?);
+                        }
+                    }
+                }
+                return Ok(values);
+            }
+        }
+    }
+}
+
+/// Parse the header of a PLY file
+/// # Arguments
+/// * 'header' - Every line of the header, up to and including end_header
+/// * 'path' - Path of the file, used in error messages
+/// # Returns
+/// * How the body is stored and the elements it holds, in order
+fn parse_header(header: &str, path: &str) -> Result<(Format, Vec<Element>), String> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for (number, line) in header.lines().enumerate() {
+        let error = |message: String| format!("{}:{}: {}", path, number + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["ply"] | ["end_header"] | [] => {},
+            ["comment", ..] | ["obj_info", ..] => {},
+            ["format", kind, _version] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format '{}'", kind)))
+                });
+            },
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| error(format!("'{}' is not a valid element count", count)))?;
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            },
+            ["property", "list", count, item, name] => {
+                let scalar = |t: &str| Scalar::parse(t).ok_or_else(|| error(format!("unknown property type '{}'", t)));
+                let property = Property::List { name: name.to_string(), count: scalar(count)?, item: scalar(item)? };
+                elements.last_mut().ok_or_else(|| error("property before any element".to_string()))?.properties.push(property);
+            },
+            ["property", kind, name] => {
+                let kind = Scalar::parse(kind).ok_or_else(|| error(format!("unknown property type '{}'", kind)))?;
+                let property = Property::Value { name: name.to_string(), kind };
+                elements.last_mut().ok_or_else(|| error("property before any element".to_string()))?.properties.push(property);
+            },
+            _ => return Err(error(format!("unexpected header line '{}'", line)))
+        }
+    }
+    return Ok((format.ok_or(format!("{}: header has no format line", path))?, elements));
+}
+
+/// Load an ASCII or binary PLY mesh
+/// Vertex normals, colors and texture coordinates are used when there are any, faces with more than 3 vertices are split into a fan
+/// # Arguments
+/// * 'path' - Path of a PLY file
+/// * 'smooth' - Whether the mesh is smooth shaded, normals are averaged from the faces if it has none
+/// # Returns
+/// * The mesh, whose default material shows its vertex colors if it has them, or a message pointing at what couldn't be read
+pub fn load_ply(path: &str, smooth: bool) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+    return parse_ply(&bytes, path, smooth);
+}
+
+/// Read a PLY mesh from the contents of a file, see load_ply
+/// # Arguments
+/// * 'bytes' - The whole file
+/// * 'path' - Path of the file, used in error messages
+/// * 'smooth' - Whether the mesh is smooth shaded
+fn parse_ply(bytes: &[u8], path: &str, smooth: bool) -> Result<Mesh, String> {
+    if !bytes.starts_with(b"ply") {
+        return Err(format!("{}: not a PLY file", path));
+    }
+
+    // The header is always text, the body starts right after the end_header line
+    let marker = b"end_header";
+    let end = bytes.windows(marker.len()).position(|w| w == marker).ok_or(format!("{}: header has no end_header", path))?;
+    let body_start = bytes[end..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| end + i + 1);
+    let header = String::from_utf8_lossy(&bytes[..body_start]).to_string();
+    let (format, elements) = parse_header(&header, path)?;
+
+    let text;
+    let mut body = match format {
+        Format::Ascii => {
+            text = String::from_utf8_lossy(&bytes[body_start..]).to_string();
+            Body::Ascii { lines: text.lines().enumerate(), first_line: header.lines().count() }
+        },
+        Format::BinaryLittleEndian => Body::Binary { bytes: &bytes[body_start..], position: 0, big_endian: false },
+        Format::BinaryBigEndian => Body::Binary { bytes: &bytes[body_start..], position: 0, big_endian: true }
+    };
+
+    let mut points: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut colors: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec3> = Vec::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+
+    // Elements have to be read in order, even ones like edges that aren't used
+    for element in elements.iter() {
+        match element.name.as_str() {
+            "vertex" => {
+                let position = [element.find(&["x"]), element.find(&["y"]), element.find(&["z"])];
+                let [Some(x), Some(y), Some(z)] = position else {
+                    return Err(format!("{}: vertices need x, y and z properties", path));
+                };
+                let normal = [element.find(&["nx"]), element.find(&["ny"]), element.find(&["nz"])];
+                let color = [element.find(&["red", "diffuse_red"]), element.find(&["green", "diffuse_green"]), element.find(&["blue", "diffuse_blue"])];
+                let uv = [element.find(&["u", "s", "texture_u", "texture_s"]), element.find(&["v", "t", "texture_v", "texture_t"])];
+
+                // Colors are sRGB, either 0 to 255 or 0 to 1 depending on how they're stored
+                let color_scale = match color[0].map(|i| &element.properties[i]) {
+                    Some(Property::Value { kind, .. }) if kind.is_float() => 1.0,
+                    _ => 1.0 / 255.0
+                };
+
+                for _ in 0..element.count {
+                    let v = body.read(element, path)?;
+                    points.push(Vec3::new(v[x][0], v[y][0], v[z][0]));
+                    if let [Some(nx), Some(ny), Some(nz)] = normal {
+                        normals.push(Vec3::new(v[nx][0], v[ny][0], v[nz][0]));
+                    }
+                    if let [Some(r), Some(g), Some(b)] = color {
+                        let channel = |c: f64| srgb_to_linear(((c * color_scale).clamp(0.0, 1.0) * 255.0).round() as u8);
+                        colors.push(Vec3::new(channel(v[r][0]), channel(v[g][0]), channel(v[b][0])));
+                    }
+                    if let [Some(u), Some(t)] = uv {
+                        uvs.push(Vec3::new(v[u][0], v[t][0], 0.0));
+                    }
+                }
+            },
+            "face" => {
+                let indices = element.find(&["vertex_indices", "vertex_index"])
+                    .ok_or(format!("{}: faces need a vertex_indices property", path))?;
+                for _ in 0..element.count {
+                    let v = body.read(element, path)?;
+
+                    // Indices stored as floats, or negative ones, would otherwise be cut down to some other vertex
+                    if let Some(i) = v[indices].iter().find(|i| i.fract() != 0.0 || **i < 0.0) {
+                        return Err(format!("{}: face {} uses vertex {}, indices must be whole numbers 0 or above", path, faces.len(), i));
+                    }
+                    faces.push(v[indices].iter().map(|&i| i as usize).collect());
+                }
+            },
+            _ => {
+                for _ in 0..element.count {
+                    body.read(element, path)?;
+                }
+            }
+        }
+    }
+
+    let mut mesh = MeshBuilder::new();
+    for (number, face) in faces.iter().enumerate() {
+        if face.len() < 3 {
+            return Err(format!("{}: face {} has fewer than 3 vertices", path, number));
+        }
+        if let Some(i) = face.iter().find(|&&i| i >= points.len()) {
+            return Err(format!("{}: face {} uses vertex {}, but there are only {}", path, number, i, points.len()));
+        }
+
+        // Split the face into a fan of triangles around its first vertex
+        for i in 1..face.len() - 1 {
+            let corners = [face[0], face[i], face[i + 1]].map(|c| {
+                let mut vertex = Vertex::new((c, 0), points[c]);
+                vertex.normal = normals.get(c).map(|&n| unit_vector(n));
+                vertex.color = colors.get(c).copied().unwrap_or(vertex.color);
+                vertex.uv = uvs.get(c).copied().unwrap_or(vertex.uv);
+                return vertex;
+            });
+            mesh.add_triangle(corners, smooth, 0);
+        }
+    }
+
+    let mut mesh = mesh.build();
+    if !colors.is_empty() {
+        mesh.set_material(MaterialEnum::Diffuse(Diffuse::new(Texture::Vertex)));
+    }
+    return Ok(mesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square as a single quad, followed by the given face
+    fn square(face: &str) -> String {
+        return format!("ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+element face 2
+property list uchar float vertex_indices
+end_header
+0 0 0
+1 0 0
+1 1 0
+0 1 0
+4 0 1 2 3
+{}
+", face);
+    }
+
+    fn parse(text: &str) -> Result<Mesh, String> {
+        return parse_ply(text.as_bytes(), "square.ply", false);
+    }
+
+    /// A colored triangle in a binary file, with a 2 byte and a 4 byte type so the byte order matters
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = format!("ply
+format {} 1.0
+comment written by hand
+element vertex 3
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element extra 1
+property ushort unused
+end_header
+", format).into_bytes();
+
+        let f = |v: f32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let i = |v: i32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        for (position, color) in [([0.0, 0.0, 0.0], [255, 0, 0]), ([2.0, 0.0, 0.0], [0, 255, 0]), ([0.0, 0.5, -1.0], [0, 0, 255])] {
+            for v in position {
+                bytes.extend(f(v));
+            }
+            bytes.extend(color);
+        }
+        bytes.push(3);
+        for v in [0, 1, 2] {
+            bytes.extend(i(v));
+        }
+        bytes.extend(if big_endian { 7u16.to_be_bytes() } else { 7u16.to_le_bytes() });
+        return bytes;
+    }
+
+    #[test]
+    fn quads_are_split_into_a_fan() {
+        let mesh = parse(&square("3 0 2 3")).unwrap();
+        assert_eq!(mesh.triangles.len(), 3);
+        assert_eq!(mesh.triangles[1].vertices, [0, 2, 3]);
+    }
+
+    #[test]
+    fn binary_files_read_the_same_either_way_round() {
+        for big_endian in [false, true] {
+            let mesh = parse_ply(&binary(big_endian), "triangle.ply", false).unwrap();
+            assert_eq!(mesh.triangles.len(), 1);
+            let points = mesh.triangles[0].points(&mesh.positions);
+            assert_eq!([points[1].x, points[2].y, points[2].z], [2.0, 0.5, -1.0]);
+
+            // uchar colors go from 0 to 255 and are sRGB, so full channels come out as 1.0
+            let colors = mesh.triangles[0].points(&mesh.colors);
+            assert_eq!([colors[0].x, colors[1].y, colors[2].z], [1.0, 1.0, 1.0]);
+            assert_eq!([colors[0].y, colors[1].z, colors[2].x], [0.0, 0.0, 0.0]);
+            assert!(matches!(mesh.materials[0], MaterialEnum::Diffuse(_)));
+        }
+    }
+
+    #[test]
+    fn binary_file_cut_short_is_an_error() {
+        let bytes = binary(false);
+        let error = parse_ply(&bytes[..bytes.len() - 6], "triangle.ply", false).unwrap_err();
+        assert_eq!(error, "triangle.ply: file ends in the middle of the face entries");
+    }
+
+    #[test]
+    fn negative_index_is_an_error() {
+        let error = parse(&square("3 0 -1 2")).unwrap_err();
+        assert_eq!(error, "square.ply: face 1 uses vertex -1, indices must be whole numbers 0 or above");
+    }
+
+    #[test]
+    fn fractional_index_is_an_error() {
+        let error = parse(&square("3 0 1.5 2")).unwrap_err();
+        assert_eq!(error, "square.ply: face 1 uses vertex 1.5, indices must be whole numbers 0 or above");
+    }
+
+    #[test]
+    fn index_past_the_vertices_is_an_error() {
+        let error = parse(&square("3 0 1 4")).unwrap_err();
+        assert_eq!(error, "square.ply: face 1 uses vertex 4, but there are only 4");
+    }
+}
@@ -0,0 +1,32 @@
+use std::cell::RefCell;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+thread_local! {
+    /// Every thread gets its own generator so rendering threads never wait on each other
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Reseed the current thread's random number generator
+/// Everything random drawn afterwards on this thread is determined by the seed
+/// # Arguments
+/// * 'seed' - New seed
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Generate a random double in [0, 1)
+pub fn random_double() -> f64 {
+    return RNG.with(|rng| rng.borrow_mut().gen::<f64>());
+}
+
+/// Generate a random double in [min, max)
+/// # Arguments
+/// * 'min, max' - Range of the random number
+pub fn random_range(min: f64, max: f64) -> f64 {
+    return RNG.with(|rng| rng.borrow_mut().gen_range(min..max));
+}
+
+/// Generate a random u64, used to pick a seed when none is given
+pub fn random_u64() -> u64 {
+    return RNG.with(|rng| rng.borrow_mut().gen::<u64>());
+}
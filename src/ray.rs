@@ -1,22 +1,38 @@
 use crate::vec3::Vec3;
 
+/// Hits closer than this to a ray's origin are ignored, stops bounced rays from hitting the surface they left
+pub const RAY_EPSILON: f64 = 0.0001;
+
 /// Ray struct
 #[derive(Copy, Clone, Debug)]
 pub struct Ray {
     /// Where the ray begins
     pub origin: Vec3,
     /// The direction the ray is pointing
-    pub direction: Vec3
+    pub direction: Vec3,
+    /// Closest t along the ray a hit may be at
+    pub t_min: f64,
+    /// Furthest t along the ray a hit may be at, shrinks as closer hits are found
+    pub t_max: f64
 }
 
 impl Ray {
 
-    /// Create a new ray
+    /// Create a new ray that can hit anything in front of it
     /// # Arguments
     /// * 'origin' - Ray starting position
     /// * 'direction' - Ray direction
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        return Ray { origin, direction };
+        return Ray { origin, direction, t_min: RAY_EPSILON, t_max: f64::INFINITY };
+    }
+
+    /// Create a new ray that can only hit things within an interval
+    /// # Arguments
+    /// * 'origin' - Ray starting position
+    /// * 'direction' - Ray direction
+    /// * 't_min, t_max' - Interval along the ray hits must be in
+    pub fn new_bounded(origin: Vec3, direction: Vec3, t_min: f64, t_max: f64) -> Ray {
+        return Ray { origin, direction, t_min, t_max };
     }
 
     /// Calculate the at position on a ray given t
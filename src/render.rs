@@ -0,0 +1,328 @@
+use std::{str::FromStr, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, thread};
+use serde::Deserialize;
+use crate::{vec3::{Vec3, unit_vector}, ray::{Ray, RAY_EPSILON}, world::World, material::Material, camera::Camera, framebuffer::Framebuffer,
+    random::{self, random_double}};
+
+/// Width and height in pixels of the tiles the image is split into
+const TILE_SIZE: u32 = 32;
+
+/// Determine which drawing mode to use
+/// * 'Colors' - Draw only the colors of the objects 
+/// * 'Normals' - Draw only the normals of the objects
+/// * 'Samples' - Draw the final image with sampling
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrawingMode {
+    Colors,
+    Normals,
+    Samples
+}
+
+/// Allow the drawing mode to be given by name on the command line
+impl FromStr for DrawingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DrawingMode, String> {
+        match s {
+            "colors" => return Ok(DrawingMode::Colors),
+            "normals" => return Ok(DrawingMode::Normals),
+            "samples" => return Ok(DrawingMode::Samples),
+            _ => return Err(format!("unknown drawing mode '{}', expected colors, normals or samples", s))
+        }
+    }
+}
+
+/// Calculate color based on the ray and whatever it hits
+/// # Arguments
+/// * 'r' - Ray to cast
+/// * 'w' - World which contains all objects 
+/// * 'depth' - Number of bounces a ray can have
+/// * 'mode' - Drawing mode
+/// # Returns
+/// * Vec3 which contains r,g,b values in the x,y,z position of the vector
+pub fn ray_color(r: Ray, w: &World, depth: u32, mode: DrawingMode) -> Vec3 {
+
+    // Match the drawing mode
+    match mode {
+        DrawingMode::Colors => {
+
+            // Check if our ray hits any object
+            // Hit will contain details about the object the ray hit
+            let hit = w.hit(r);
+
+            // Hit.t will be > 0 if the ray actually hit something
+            if hit.t > 0.0 {
+
+                // Simply return the color of what the ray hit
+                return hit.material.get_albedo(&hit);
+            }
+        },
+        DrawingMode::Normals => {
+            let hit = w.hit(r);
+            if hit.t > 0.0 {
+                // Show the normal pointing out of the triangle, not the one flipped to face the ray
+                let n = if hit.front_face { hit.normal } else { -hit.normal };
+
+                // Calculate color based on the normal
+                return Vec3::new(n.x+1.0, n.y+1.0, n.z+1.0) * 0.5;
+            }
+        },
+        DrawingMode::Samples => {
+
+            // Samples mode follows the ray through every bounce
+            return trace(r, w, depth);
+        }
+    }
+
+    return w.background.value(r.direction);
+}
+
+/// Weight a sample taken with one strategy against another that could have taken it too
+/// # Arguments
+/// * 'pdf' - Density of the strategy the sample was taken with
+/// * 'other_pdf' - Density of the other strategy
+/// # Credit
+/// * Veach's power heuristic, <https://pbr-book.org/3ed-2018/Monte_Carlo_Integration/Importance_Sampling#MultipleImportanceSampling>
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    return a / (a + b);
+}
+
+/// Follow a path through the world and gather the light arriving along it
+/// Every non specular bounce samples a light directly as well as scattering, the two are combined with multiple importance sampling
+/// # Arguments
+/// * 'r' - Camera ray the path starts with
+/// * 'w' - World which contains all objects
+/// * 'max_depth' - Number of bounces a ray can have
+/// # Returns
+/// * Light arriving along the ray
+fn trace(r: Ray, w: &World, max_depth: u32) -> Vec3 {
+    let mut color = Vec3::new(0.0, 0.0, 0.0);
+
+    // Fraction of light that makes it from the current bounce back to the camera
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = r;
+
+    // Light sampling can't have found what camera rays and mirror or glass bounces hit, so nothing is weighted for those
+    let mut specular_bounce = true;
+    let mut scatter_pdf = 0.0;
+
+    for bounce in 0..max_depth {
+        let hit = w.hit(ray);
+        if hit.t <= 0.0 {
+            let background = w.background.value(ray.direction);
+            if specular_bounce {
+                color = color + throughput * background;
+            } else {
+                // An environment map may have been sampled at the previous bounce too
+                color = color + throughput * background * power_heuristic(scatter_pdf, w.background_pdf(ray));
+            }
+            break;
+        }
+
+        // Light given off by the object itself, only lights give off any
+        let emitted = hit.material.emitted(&hit);
+        if specular_bounce {
+            color = color + throughput * emitted;
+        } else {
+            // The previous bounce sampled this light as well, only count this path's share of it
+            let weight = power_heuristic(scatter_pdf, w.light_pdf(ray, &hit));
+            color = color + throughput * emitted * weight;
+        }
+
+        // Sample a light directly, it counts as the next bounce so only if there's one left
+        if !hit.material.is_specular() && bounce + 1 < max_depth {
+            if let Some(light) = w.sample_light(hit.at) {
+                let f = hit.material.eval(ray, &hit, light.direction);
+
+                // Stop just short of the light so it doesn't shadow itself
+                let shadow = Ray::new_bounded(hit.at, light.direction, RAY_EPSILON, light.distance - RAY_EPSILON);
+                if !f.near_zero() && w.hit(shadow).t <= 0.0 {
+                    let weight = power_heuristic(light.pdf, hit.material.pdf(ray, &hit, light.direction));
+                    color = color + throughput * f * light.emission * (weight / light.pdf);
+                }
+            }
+        }
+
+        // Point, spot and directional lights can't be hit by scattering, so every one of them is always sampled
+        if bounce + 1 < max_depth {
+            for light in w.punctual_lights.iter() {
+                if let Some(light) = light.illuminate(hit.at) {
+                    let f = hit.material.eval(ray, &hit, light.direction);
+                    if f.near_zero() {
+                        continue;
+                    }
+                    let shadow = Ray::new_bounded(hit.at, light.direction, RAY_EPSILON, light.distance - RAY_EPSILON);
+                    if w.hit(shadow).t <= 0.0 {
+                        color = color + throughput * f * light.emission;
+                    }
+                }
+            }
+        }
+
+        // Will store the new ray, i.e. we bounce off the object and have a new ray based on the bounce
+        let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+
+        // Store the current color of whatever the ray bounces off
+        let mut attenuation = Vec3::new(0.0, 0.0, 0.0);
+
+        // Nothing bounced, either a light or the ray got absorbed
+        if !hit.material.scatter(ray, hit.clone(), &mut attenuation, &mut scattered) {
+            break;
+        }
+
+        specular_bounce = hit.material.is_specular();
+        scatter_pdf = hit.material.pdf(ray, &hit, unit_vector(scattered.direction));
+        throughput = throughput * attenuation;
+        ray = scattered;
+    }
+    return color;
+}
+
+/// Settings shared by every tile of a render
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSettings {
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+    /// How many samples are taken per pixel in samples mode
+    pub samples: u32,
+    /// Number of bounces a ray can perform
+    pub max_depth: u32,
+    /// Drawing mode
+    pub mode: DrawingMode,
+    /// Seed every tile's random numbers are derived from
+    pub seed: u64
+}
+
+/// Rectangular block of pixels, rendered by one thread
+#[derive(Copy, Clone)]
+struct Tile {
+    /// Column of the tile's left edge
+    x: u32,
+    /// Row of the tile's top edge, row 0 is the top of the image
+    y: u32,
+    width: u32,
+    height: u32
+}
+
+/// Split the image into tiles
+/// # Arguments
+/// * 'width, height' - Image size in pixels
+/// # Returns
+/// * Tiles covering the whole image, row by row from the top left
+fn tiles(width: u32, height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(TILE_SIZE as usize) {
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - y)
+            });
+        }
+    }
+    return tiles;
+}
+
+/// Calculate the seed for a tile's random numbers
+/// Depends only on the render seed and which tile it is, never on which thread renders it
+fn tile_seed(seed: u64, index: usize) -> u64 {
+    return seed ^ (index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+}
+
+/// Render every pixel within a tile
+/// # Arguments
+/// * 'tile' - The tile to render
+/// * 'w' - World which contains all objects
+/// * 'cam' - Camera the image is seen through
+/// * 'settings' - Render settings
+/// # Returns
+/// * Colors of the tile's pixels, row by row from the top. Samples mode colors are averaged over every sample
+fn render_tile(tile: Tile, w: &World, cam: &Camera, settings: RenderSettings) -> Vec<Vec3> {
+    let mut colors = Vec::with_capacity((tile.width * tile.height) as usize);
+
+    for row in tile.y..tile.y + tile.height {
+
+        // Flip, the camera's v goes up while rows go down
+        let y = settings.height - 1 - row;
+        for x in tile.x..tile.x + tile.width {
+            match settings.mode {
+                DrawingMode::Colors | DrawingMode::Normals => {
+                    let u = x as f64 / (settings.width - 1) as f64;
+                    let v = y as f64 / (settings.height - 1) as f64;
+
+                    // Send over the ray and world and figure out the color we should draw for this pixel
+                    colors.push(ray_color(cam.get_ray(u, v), w, settings.max_depth, settings.mode));
+                },
+                DrawingMode::Samples => {
+                    let mut color = Vec3::new(0.0, 0.0, 0.0);
+
+                    // Loop for however many samples we want to take
+                    for _ in 0..settings.samples {
+
+                        // Calculate u&v based on our random samples
+                        let u = (x as f64 + random_double()) / (settings.width - 1) as f64;
+                        let v = (y as f64 + random_double()) / (settings.height - 1) as f64;
+
+                        // Add to the color for each sample, essentially creating an average color
+                        color = color + ray_color(cam.get_ray(u, v), w, settings.max_depth, settings.mode);
+                    }
+                    colors.push(color / settings.samples.max(1) as f64);
+                }
+            }
+        }
+    }
+    return colors;
+}
+
+/// Render the whole image, spreading tiles across every core
+/// # Arguments
+/// * 'w' - World which contains all objects, shared read-only by every thread
+/// * 'cam' - Camera the image is seen through
+/// * 'settings' - Render settings
+/// # Returns
+/// * Framebuffer holding the linear color of every pixel
+pub fn render(w: &World, cam: &Camera, settings: RenderSettings) -> Framebuffer {
+    let tiles = tiles(settings.width, settings.height);
+    let framebuffer = Mutex::new(Framebuffer::new(settings.width, settings.height));
+
+    // Threads grab the next tile nobody has started yet until none are left
+    let next_tile = AtomicUsize::new(0);
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if index >= tiles.len() {
+                        break;
+                    }
+                    let tile = tiles[index];
+
+                    // Reseed so the tile comes out the same no matter which thread picked it up
+                    random::seed(tile_seed(settings.seed, index));
+                    let colors = render_tile(tile, w, cam, settings);
+
+                    // Copy the finished tile into the image
+                    let mut framebuffer = framebuffer.lock().unwrap();
+                    for row in 0..tile.height {
+                        let src = (row * tile.width) as usize;
+                        let dst = ((tile.y + row) * settings.width + tile.x) as usize;
+                        framebuffer.pixels[dst..dst + tile.width as usize].copy_from_slice(&colors[src..src + tile.width as usize]);
+                    }
+                    println!("Tiles remaining: {}", tiles.len() - index - 1);
+                }
+            });
+        }
+    });
+
+    return framebuffer.into_inner().unwrap();
+}
@@ -0,0 +1,793 @@
+use std::{fmt, fs, path::{Path, PathBuf}, sync::Arc};
+use serde::Deserialize;
+use toml::Spanned;
+use crate::{vec3::{Vec3, unit_vector, cross}, camera::Camera, world::World, transform::Transform, instance::Instance,
+    hittable::HittableEnum, shapes::{Sphere, Plane, Disk, AaBox, Cylinder}, mesh::load_mesh, ply::load_ply, stl::load_stl, gltf_loader::{load_gltf, GltfCamera}, random, render::{DrawingMode, RenderSettings},
+    material::{MaterialEnum, Diffuse, Metal, Dielectric, Emissive, Principled},
+    texture::{Texture, ImageTexture, WrapMode, Perlin, NormalMap}, light::{PunctualLight, PointLight, SpotLight, DirectionalLight}, background::{Background, EnvironmentMap}};
+
+/// Error found while loading a scene file
+#[derive(Debug)]
+pub struct SceneError {
+    /// Scene file the error is in
+    pub path: PathBuf,
+    /// Line the error is on, if it can be pinned to one
+    pub line: Option<usize>,
+    /// What went wrong
+    pub message: String
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message)
+        }
+    }
+}
+
+/// [render] table of a scene file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, default)]
+struct RenderDescription {
+    width: u32,
+    height: u32,
+    samples: u32,
+    max_depth: u32,
+    mode: DrawingMode,
+    /// Random seed, a new one is picked every run if left out
+    seed: Option<u64>
+}
+
+impl Default for RenderDescription {
+    fn default() -> RenderDescription {
+        return RenderDescription { width: 480, height: 270, samples: 3, max_depth: 5, mode: DrawingMode::Samples, seed: None };
+    }
+}
+
+/// [camera] table of a scene file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    look_from: Vec3,
+    look_at: Vec3,
+    #[serde(default = "default_up")]
+    up: Vec3,
+    /// Vertical field of view in degrees
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    /// Defaults to the distance between look_from and look_at
+    focus_dist: Option<f64>
+}
+
+fn default_up() -> Vec3 {
+    return Vec3::new(0.0, 1.0, 0.0);
+}
+
+/// Color of a material, either a plain [r, g, b], a single gray value or a texture table
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum TextureDescription {
+    Value(f64),
+    Color(Vec3),
+    Map(TextureMapDescription)
+}
+
+/// Texture table, picked by its type key
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum TextureMapDescription {
+    Image {
+        /// PNG or JPEG image, relative to the scene file
+        path: String,
+        /// "repeat", "clamp" or "mirror"
+        #[serde(default)]
+        wrap: WrapMode
+    },
+    /// Vertex colors of a PLY mesh, white on meshes without any
+    Vertex,
+    /// 3D checkerboard, each cell is scale units wide
+    Checker {
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+        #[serde(default = "default_scale")]
+        scale: f64
+    },
+    Noise {
+        #[serde(default = "default_black")]
+        low: Vec3,
+        #[serde(default = "default_white")]
+        high: Vec3,
+        #[serde(default = "default_scale")]
+        scale: f64
+    },
+    Turbulence {
+        #[serde(default = "default_black")]
+        low: Vec3,
+        #[serde(default = "default_white")]
+        high: Vec3,
+        #[serde(default = "default_scale")]
+        scale: f64
+    },
+    Marble {
+        #[serde(default = "default_black")]
+        low: Vec3,
+        #[serde(default = "default_white")]
+        high: Vec3,
+        #[serde(default = "default_scale")]
+        scale: f64
+    }
+}
+
+fn default_black() -> Vec3 {
+    return Vec3::new(0.0, 0.0, 0.0);
+}
+
+/// Material of a [[mesh]], picked by its type key
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialDescription {
+    Diffuse {
+        albedo: TextureDescription,
+        normal_map: Option<NormalMapDescription>
+    },
+    Metal {
+        albedo: TextureDescription,
+        smoothness: f64,
+        normal_map: Option<NormalMapDescription>
+    },
+    Dielectric {
+        #[serde(default = "default_white")]
+        albedo: Vec3,
+        /// Index of refraction
+        ir: f64
+    },
+    Emissive {
+        color: Vec3,
+        #[serde(default = "default_strength")]
+        strength: f64
+    },
+    /// glTF style metallic-roughness material
+    Principled {
+        base_color: TextureDescription,
+        #[serde(default = "default_metallic")]
+        metallic: TextureDescription,
+        #[serde(default = "default_roughness")]
+        roughness: TextureDescription,
+        #[serde(default = "default_specular")]
+        specular: f64,
+        normal_map: Option<NormalMapDescription>
+    }
+}
+
+/// normal_map table of a material, picked by its type key
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum NormalMapDescription {
+    /// Tangent space normal map image, relative to the scene file
+    Tangent {
+        path: String,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default = "default_strength")]
+        strength: f64
+    },
+    /// Height map, any texture
+    Bump {
+        height: TextureDescription,
+        #[serde(default = "default_strength")]
+        strength: f64
+    }
+}
+
+fn default_metallic() -> TextureDescription {
+    return TextureDescription::Value(0.0);
+}
+
+fn default_roughness() -> TextureDescription {
+    return TextureDescription::Value(0.5);
+}
+
+fn default_specular() -> f64 {
+    return 0.5;
+}
+
+fn default_strength() -> f64 {
+    return 1.0;
+}
+
+fn default_white() -> Vec3 {
+    return Vec3::new(1.0, 1.0, 1.0);
+}
+
+/// One [[mesh]] entry of a scene file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct MeshDescription {
+    /// OBJ, PLY or STL file, relative to the scene file
+    path: String,
+    #[serde(default)]
+    smooth: bool,
+    /// Transforms are applied scale first, then rotate, then translate
+    #[serde(default)]
+    scale: ScaleDescription,
+    rotate: Option<RotationDescription>,
+    translate: Option<Vec3>,
+    /// Replaces every material from the OBJ file's material library
+    material: Option<MaterialDescription>,
+    /// Copies of the mesh sharing its triangles, placed after the mesh's own transform
+    /// The mesh is only placed through these when there are any
+    #[serde(default)]
+    instance: Vec<InstanceDescription>
+}
+
+fn default_scale() -> f64 {
+    return 1.0;
+}
+
+/// One [[mesh.instance]] entry of a scene file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct InstanceDescription {
+    /// Transforms are applied scale first, then rotate, then translate
+    #[serde(default)]
+    scale: ScaleDescription,
+    rotate: Option<RotationDescription>,
+    translate: Option<Vec3>,
+    /// Replaces the mesh's materials on this instance only
+    material: Option<MaterialDescription>
+}
+
+/// Scale of a mesh, one number for every axis or [x, y, z]
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "scale must be a number or [x, y, z]")]
+enum ScaleDescription {
+    Uniform(f64),
+    Axes(Vec3)
+}
+
+impl Default for ScaleDescription {
+    fn default() -> ScaleDescription {
+        return ScaleDescription::Uniform(1.0);
+    }
+}
+
+/// Rotation of a mesh
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "rotate must be [x, y, z] in degrees, a quaternion [x, y, z, w] or { axis = [x, y, z], angle = degrees }")]
+enum RotationDescription {
+    /// Degrees on x, then y, then z
+    Euler(Vec3),
+    Quaternion([f64; 4]),
+    AxisAngle {
+        axis: Vec3,
+        /// Degrees, counterclockwise looking down the axis
+        angle: f64
+    }
+}
+
+/// Build the transform a mesh or instance is placed with
+/// # Arguments
+/// * 'scale, rotate, translate' - The entry's transforms, applied in that order
+/// # Returns
+/// * The transform, or a message if the rotation can't be used
+fn build_transform(scale: &ScaleDescription, rotate: &Option<RotationDescription>, translate: Option<Vec3>) -> Result<Transform, String> {
+    let scale = match scale {
+        ScaleDescription::Uniform(s) => Vec3::new(*s, *s, *s),
+        ScaleDescription::Axes(s) => *s
+    };
+    let rotate = match rotate {
+        None => Transform::identity(),
+        Some(RotationDescription::Euler(r)) => Transform::rotate(*r),
+        Some(RotationDescription::Quaternion(q)) if q.iter().all(|&c| c == 0.0) => return Err("a quaternion of all zeros isn't a rotation".to_string()),
+        Some(RotationDescription::Quaternion(q)) => Transform::quaternion(*q),
+        Some(RotationDescription::AxisAngle { axis, .. }) if axis.near_zero() => return Err("rotation axis can't be all zeros".to_string()),
+        Some(RotationDescription::AxisAngle { axis, angle }) => Transform::axis_angle(*axis, *angle)
+    };
+    let translate = Transform::translate(translate.unwrap_or(Vec3::new(0.0, 0.0, 0.0)));
+    return Ok(Transform::scale(scale).then(&rotate).then(&translate));
+}
+
+/// One [[gltf]] entry of a scene file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct GltfDescription {
+    /// .gltf or .glb file, relative to the scene file
+    path: String
+}
+
+/// One [[light]] entry of a scene file, picked by its type key
+/// The light given off is color multiplied by strength
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum LightDescription {
+    Point {
+        position: Vec3,
+        #[serde(default = "default_white")]
+        color: Vec3,
+        #[serde(default = "default_strength")]
+        strength: f64
+    },
+    Spot {
+        position: Vec3,
+        /// Direction the cone points along
+        direction: Vec3,
+        #[serde(default = "default_white")]
+        color: Vec3,
+        #[serde(default = "default_strength")]
+        strength: f64,
+        /// Angle from the axis in degrees where the light starts fading out, defaults to a hard edge
+        inner_angle: Option<f64>,
+        /// Angle from the axis in degrees past which there's no light
+        outer_angle: f64
+    },
+    Directional {
+        /// Direction the light travels along
+        direction: Vec3,
+        #[serde(default = "default_white")]
+        color: Vec3,
+        #[serde(default = "default_strength")]
+        strength: f64
+    }
+}
+
+/// One [[shape]] entry of a scene file, picked by its type key
+/// Shapes are exact, so a sphere doesn't need a finely divided model to look round
+/// Without a material they're plain diffuse, like meshes
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ShapeDescription {
+    Sphere {
+        center: Vec3,
+        radius: f64,
+        material: Option<MaterialDescription>
+    },
+    /// Goes on forever, facing the side its normal points to
+    Plane {
+        point: Vec3,
+        normal: Vec3,
+        material: Option<MaterialDescription>
+    },
+    Disk {
+        center: Vec3,
+        normal: Vec3,
+        radius: f64,
+        material: Option<MaterialDescription>
+    },
+    /// Box lined up with the x, y and z axes
+    #[serde(rename = "box")]
+    AaBox {
+        /// Opposite corners
+        min: Vec3,
+        max: Vec3,
+        material: Option<MaterialDescription>
+    },
+    /// Closed at both ends
+    Cylinder {
+        /// Center of the bottom end
+        base: Vec3,
+        /// Direction from the bottom end towards the top
+        #[serde(default = "default_up")]
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        material: Option<MaterialDescription>
+    }
+}
+
+/// Build a shape from its scene file description
+/// # Arguments
+/// * 'description' - The [[shape]] entry
+/// * 'base' - Directory of the scene file, textures are relative to it
+/// # Returns
+/// * The shape, or a message if it has no size or its material can't be built
+fn build_shape(description: &ShapeDescription, base: &Path) -> Result<HittableEnum, String> {
+    let material = |m: &Option<MaterialDescription>| -> Result<MaterialEnum, String> {
+        match m {
+            Some(m) => return build_material(m, base),
+            None => return Ok(MaterialEnum::Diffuse(Diffuse::new(Texture::Solid(Vec3::new(0.5, 0.5, 0.5)))))
+        }
+    };
+    let direction = |v: &Vec3, name: &str| -> Result<Vec3, String> {
+        if v.near_zero() {
+            return Err(format!("shape {} can't be [0, 0, 0]", name));
+        }
+        return Ok(unit_vector(*v));
+    };
+    let positive = |v: f64, name: &str| -> Result<f64, String> {
+        if v <= 0.0 {
+            return Err(format!("shape {} must be more than 0", name));
+        }
+        return Ok(v);
+    };
+
+    match description {
+        ShapeDescription::Sphere { center, radius, material: m } => {
+            return Ok(HittableEnum::Sphere(Sphere::new(*center, positive(*radius, "radius")?, material(m)?)));
+        },
+        ShapeDescription::Plane { point, normal, material: m } => {
+            return Ok(HittableEnum::Plane(Plane::new(*point, direction(normal, "normal")?, material(m)?)));
+        },
+        ShapeDescription::Disk { center, normal, radius, material: m } => {
+            return Ok(HittableEnum::Disk(Disk::new(*center, direction(normal, "normal")?, positive(*radius, "radius")?, material(m)?)));
+        },
+        ShapeDescription::AaBox { min, max, material: m } => {
+            let size = *max - *min;
+            if size.x == 0.0 || size.y == 0.0 || size.z == 0.0 {
+                return Err("box min and max must differ on every axis".to_string());
+            }
+            return Ok(HittableEnum::AaBox(AaBox::new(*min, *max, material(m)?)));
+        },
+        ShapeDescription::Cylinder { base: bottom, axis, radius, height, material: m } => {
+            let radius = positive(*radius, "radius")?;
+            let height = positive(*height, "height")?;
+            return Ok(HittableEnum::Cylinder(Cylinder::new(*bottom, direction(axis, "axis")?, radius, height, material(m)?)));
+        }
+    }
+}
+
+/// [background] table of a scene file, picked by its type key
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum BackgroundDescription {
+    Solid { color: Vec3 },
+    Gradient { bottom: Vec3, top: Vec3 },
+    Environment {
+        /// Equirectangular .hdr image, relative to the scene file
+        path: String,
+        /// Rotation around the y axis in degrees
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_strength")]
+        intensity: f64
+    }
+}
+
+/// Layout of a whole scene file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default)]
+    render: RenderDescription,
+    /// Can be left out when a glTF file has a camera
+    camera: Option<Spanned<CameraDescription>>,
+    background: Option<Spanned<BackgroundDescription>>,
+    #[serde(default)]
+    mesh: Vec<Spanned<MeshDescription>>,
+    #[serde(default)]
+    gltf: Vec<Spanned<GltfDescription>>,
+    #[serde(default)]
+    light: Vec<Spanned<LightDescription>>,
+    #[serde(default)]
+    shape: Vec<Spanned<ShapeDescription>>
+}
+
+/// Everything needed to render an image
+pub struct Scene {
+    /// All objects in the scene
+    pub world: World,
+    /// Render settings, the seed has already been picked if the file didn't give one
+    pub settings: RenderSettings,
+    /// Camera position and lens, turned into a Camera once the image size is final
+    camera: CameraDescription
+}
+
+impl Scene {
+
+    /// Create the scene's camera
+    /// # Arguments
+    /// * 'aspect_ratio' - Image width divided by image height
+    pub fn camera(&self, aspect_ratio: f64) -> Camera {
+        let c = &self.camera;
+        let focus_dist = c.focus_dist.unwrap_or_else(|| (c.look_from - c.look_at).length());
+        return Camera::new(c.look_from, c.look_at, c.up, c.vfov, aspect_ratio, c.aperture, focus_dist);
+    }
+}
+
+impl From<GltfCamera> for CameraDescription {
+    fn from(c: GltfCamera) -> CameraDescription {
+        return CameraDescription { look_from: c.position, look_at: c.position + c.forward, up: c.up, vfov: c.vfov, aperture: 0.0, focus_dist: None };
+    }
+}
+
+impl CameraDescription {
+
+    /// Check the camera can look somewhere, Camera::new doesn't
+    /// # Returns
+    /// * Nothing, or why no camera can be made from it
+    fn check(&self) -> Result<(), String> {
+        let direction = self.look_at - self.look_from;
+        if direction.near_zero() {
+            return Err("camera look_from and look_at can't be the same point".to_string());
+        }
+        if cross(unit_vector(self.up), unit_vector(direction)).near_zero() {
+            return Err("camera up can't be zero or point along the view direction".to_string());
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return Err(format!("camera vfov must be between 0 and 180 degrees, not {}", self.vfov));
+        }
+        return Ok(());
+    }
+}
+
+/// Calculate which line a byte offset into a file is on
+/// # Arguments
+/// * 'text' - Contents of the file
+/// * 'offset' - Byte offset
+/// # Returns
+/// * Line number starting from 1
+fn line_of(text: &str, offset: usize) -> usize {
+    return text[..offset.min(text.len())].matches('\n').count() + 1;
+}
+
+/// Create a texture from its description
+/// # Arguments
+/// * 'description' - Color or texture table
+/// * 'base' - Directory image paths are relative to
+fn build_texture(description: &TextureDescription, base: &Path) -> Result<Texture, String> {
+    // Every noise texture uses the same pattern, so they line up with each other
+    let noise = || Arc::new(Perlin::new(0));
+
+    // A scale of 0 squashes the whole pattern into a single point, or makes checker cells infinitely small
+    let positive_scale = |kind: &str, scale: f64| -> Result<f64, String> {
+        if scale <= 0.0 {
+            return Err(format!("{} scale must be greater than 0", kind));
+        }
+        return Ok(scale);
+    };
+
+    match description {
+        TextureDescription::Value(v) => return Ok(Texture::Solid(Vec3::new(*v, *v, *v))),
+        TextureDescription::Color(color) => return Ok(Texture::Solid(*color)),
+        TextureDescription::Map(map) => match map {
+            TextureMapDescription::Image { path, wrap } => {
+                let image = ImageTexture::load(&base.join(path).to_string_lossy(), *wrap)?;
+                return Ok(Texture::Image(Arc::new(image)));
+            },
+            TextureMapDescription::Vertex => return Ok(Texture::Vertex),
+            TextureMapDescription::Checker { even, odd, scale } => {
+                return Ok(Texture::Checker {
+                    even: Box::new(build_texture(even, base)?),
+                    odd: Box::new(build_texture(odd, base)?),
+                    scale: positive_scale("checker", *scale)?
+                });
+            },
+            TextureMapDescription::Noise { low, high, scale } => {
+                return Ok(Texture::Noise { low: *low, high: *high, scale: positive_scale("noise", *scale)?, noise: noise() });
+            },
+            TextureMapDescription::Turbulence { low, high, scale } => {
+                return Ok(Texture::Turbulence { low: *low, high: *high, scale: positive_scale("turbulence", *scale)?, noise: noise() });
+            },
+            TextureMapDescription::Marble { low, high, scale } => {
+                return Ok(Texture::Marble { low: *low, high: *high, scale: positive_scale("marble", *scale)?, noise: noise() });
+            }
+        }
+    }
+}
+
+/// Create a normal map from its description
+/// # Arguments
+/// * 'description' - normal_map table, None if the material doesn't have one
+/// * 'base' - Directory image paths are relative to
+fn build_normal_map(description: &Option<NormalMapDescription>, base: &Path) -> Result<Option<NormalMap>, String> {
+    match description {
+        None => return Ok(None),
+        Some(NormalMapDescription::Tangent { path, wrap, strength }) => {
+
+            // Normal maps hold directions, not colors, so they skip the sRGB conversion
+            let image = ImageTexture::load_linear(&base.join(path).to_string_lossy(), *wrap)?;
+            return Ok(Some(NormalMap::Tangent { image: Arc::new(image), strength: *strength }));
+        },
+        Some(NormalMapDescription::Bump { height, strength }) => {
+            return Ok(Some(NormalMap::Bump { height: build_texture(height, base)?, strength: *strength }));
+        }
+    }
+}
+
+/// Create a material from its description
+/// # Arguments
+/// * 'description' - Material table
+/// * 'base' - Directory texture paths are relative to
+fn build_material(description: &MaterialDescription, base: &Path) -> Result<MaterialEnum, String> {
+    match description {
+        MaterialDescription::Diffuse { albedo, normal_map } => {
+            let mut material = Diffuse::new(build_texture(albedo, base)?);
+            material.normal_map = build_normal_map(normal_map, base)?;
+            return Ok(MaterialEnum::Diffuse(material));
+        },
+        MaterialDescription::Metal { albedo, smoothness, normal_map } => {
+            let mut material = Metal::new(build_texture(albedo, base)?, *smoothness);
+            material.normal_map = build_normal_map(normal_map, base)?;
+            return Ok(MaterialEnum::Metal(material));
+        },
+        MaterialDescription::Dielectric { albedo, ir } => {
+
+            // Refraction divides by the index, anything 0 or below makes no sense and fills the image with NaNs
+            if *ir <= 0.0 {
+                return Err("dielectric ir must be greater than 0".to_string());
+            }
+            return Ok(MaterialEnum::Dielectric(Dielectric::new(*albedo, *ir)));
+        },
+        MaterialDescription::Emissive { color, strength } => return Ok(MaterialEnum::Emissive(Emissive::new(*color, *strength))),
+        MaterialDescription::Principled { base_color, metallic, roughness, specular, normal_map } => {
+            let base_color = build_texture(base_color, base)?;
+            let metallic = build_texture(metallic, base)?;
+            let roughness = build_texture(roughness, base)?;
+            let mut material = Principled::new(base_color, metallic, roughness, *specular);
+            material.normal_map = build_normal_map(normal_map, base)?;
+            return Ok(MaterialEnum::Principled(material));
+        }
+    }
+}
+
+/// Load a scene file
+/// # Arguments
+/// * 'path' - Path of a TOML scene file
+/// # Returns
+/// * The loaded scene, or an error pointing at the offending line
+pub fn load_scene(path: &str) -> Result<Scene, SceneError> {
+    let path = Path::new(path);
+    let error = |line: Option<usize>, message: String| SceneError { path: path.to_path_buf(), line, message };
+
+    // glTF files can be rendered on their own with the default render settings
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if extension == "gltf" || extension == "glb" {
+        return load_gltf_scene(path);
+    }
+
+    let text = fs::read_to_string(path)
+        .map_err(|e| error(None, format!("failed to read scene file: {}", e)))?;
+
+    let description: SceneDescription = toml::from_str(&text)
+        .map_err(|e| error(e.span().map(|s| line_of(&text, s.start)), e.message().to_string()))?;
+
+    // Mesh paths are relative to the scene file, not to wherever the renderer is run from
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut world = World::new();
+    for entry in description.mesh.iter() {
+        let line = Some(line_of(&text, entry.span().start));
+        let m = entry.get_ref();
+
+        let mesh_path = base.join(&m.path);
+        if !mesh_path.is_file() {
+            return Err(error(line, format!("mesh file {} does not exist", mesh_path.display())));
+        }
+
+        // The loader is picked by the file extension, anything unknown is read as OBJ
+        let file = mesh_path.to_string_lossy();
+        let mut mesh = match mesh_path.extension().map(|e| e.to_string_lossy().to_lowercase()).as_deref() {
+            Some("ply") => load_ply(&file, m.smooth),
+            Some("stl") => load_stl(&file, m.smooth),
+            _ => load_mesh(&file, m.smooth)
+        }.map_err(|e| error(line, e))?;
+
+        // The mesh's own transform is applied to its vertices once, even when it's shared
+        let transform = build_transform(&m.scale, &m.rotate, m.translate).map_err(|e| error(line, e))?;
+        if transform != Transform::identity() {
+            mesh.transform(&transform);
+        }
+        if let Some(material) = &m.material {
+            mesh.set_material(build_material(material, base).map_err(|e| error(line, e))?);
+        }
+        if m.instance.is_empty() {
+            world.add(mesh);
+            continue;
+        }
+
+        let shared = mesh.into_shared();
+        for i in m.instance.iter() {
+            let transform = build_transform(&i.scale, &i.rotate, i.translate).map_err(|e| error(line, e))?;
+            let mut instance = Instance::new(shared.clone(), transform).map_err(|e| error(line, format!("instance of {}: {}", mesh_path.display(), e)))?;
+            if let Some(material) = &i.material {
+                instance.material = Some(build_material(material, base).map_err(|e| error(line, e))?);
+            }
+            world.add_instance(instance);
+        }
+    }
+
+    // The first camera in any glTF file is used if the scene doesn't have its own
+    let mut gltf_camera = None;
+    for entry in description.gltf.iter() {
+        let line = Some(line_of(&text, entry.span().start));
+        let gltf_path = base.join(&entry.get_ref().path);
+        let camera = load_gltf(&gltf_path, &mut world).map_err(|e| error(line, format!("{}: {}", gltf_path.display(), e)))?;
+        gltf_camera = gltf_camera.or(camera.map(|c| (line, CameraDescription::from(c))));
+    }
+
+    // A missing table is reported at the top of the file, like any other missing field
+    let (line, camera) = match description.camera {
+        Some(entry) => (Some(line_of(&text, entry.span().start)), entry.into_inner()),
+        None => gltf_camera.ok_or(error(Some(1), "missing [camera], and no glTF file has one".to_string()))?
+    };
+    camera.check().map_err(|e| error(line, e))?;
+
+    for entry in description.shape.iter() {
+        let line = Some(line_of(&text, entry.span().start));
+        world.add_object(build_shape(entry.get_ref(), base).map_err(|e| error(line, e))?);
+    }
+
+    for entry in description.light.iter() {
+        let line = Some(line_of(&text, entry.span().start));
+        let light = entry.get_ref();
+
+        // A direction of all zeros can't be normalized
+        if let LightDescription::Spot { direction, .. } | LightDescription::Directional { direction, .. } = light {
+            if direction.near_zero() {
+                return Err(error(line, "light direction can't be [0, 0, 0]".to_string()));
+            }
+        }
+
+        world.add_light(match light {
+            LightDescription::Point { position, color, strength } => {
+                PunctualLight::Point(PointLight::new(*position, *color * *strength))
+            },
+            LightDescription::Spot { position, direction, color, strength, inner_angle, outer_angle } => {
+                let inner = inner_angle.unwrap_or(*outer_angle);
+                PunctualLight::Spot(SpotLight::new(*position, *direction, *color * *strength, inner, *outer_angle).map_err(|e| error(line, e))?)
+            },
+            LightDescription::Directional { direction, color, strength } => {
+                PunctualLight::Directional(DirectionalLight::new(*direction, *color * *strength))
+            }
+        });
+    }
+
+    if let Some(entry) = &description.background {
+        let line = Some(line_of(&text, entry.span().start));
+        world.background = match entry.get_ref() {
+            BackgroundDescription::Solid { color } => Background::Solid(*color),
+            BackgroundDescription::Gradient { bottom, top } => Background::Gradient { bottom: *bottom, top: *top },
+            BackgroundDescription::Environment { path, rotation, intensity } => {
+                let map_path = base.join(path);
+                let map = EnvironmentMap::load(&map_path.to_string_lossy(), *rotation, *intensity)
+                    .map_err(|e| error(line, e))?;
+                Background::Environment(Arc::new(map))
+            }
+        };
+    }
+
+    let r = &description.render;
+    if r.width < 2 || r.height < 2 {
+        return Err(error(None, "[render] image must be at least 2x2 pixels".to_string()));
+    }
+    world.build_bvh();
+    return Ok(Scene { world, settings: render_settings(r), camera });
+}
+
+/// Turn the [render] table into render settings
+/// # Arguments
+/// * 'r' - The [render] table, or its defaults
+fn render_settings(r: &RenderDescription) -> RenderSettings {
+    return RenderSettings {
+        width: r.width,
+        height: r.height,
+        samples: r.samples,
+        max_depth: r.max_depth,
+        mode: r.mode,
+        seed: r.seed.unwrap_or_else(random::random_u64)
+    };
+}
+
+/// Load a glTF file as a whole scene, rendered through its first camera with the default render settings
+/// # Arguments
+/// * 'path' - Path of a .gltf or .glb file
+/// # Returns
+/// * The loaded scene, or an error if the file can't be loaded or has no camera
+fn load_gltf_scene(path: &Path) -> Result<Scene, SceneError> {
+    let error = |message: String| SceneError { path: path.to_path_buf(), line: None, message };
+
+    let mut world = World::new();
+    let camera = load_gltf(path, &mut world).map_err(error)?
+        .ok_or(error("file has no perspective camera to render through".to_string()))?;
+    let camera_description = CameraDescription::from(camera);
+    camera_description.check().map_err(error)?;
+    world.build_bvh();
+
+    // Keep the default width, the camera may ask for a different shape
+    let mut r = RenderDescription::default();
+    if let Some(aspect_ratio) = camera.aspect_ratio.filter(|a| *a > 0.0) {
+        r.height = ((r.width as f64 / aspect_ratio).round() as u32).max(2);
+    }
+    return Ok(Scene { world, settings: render_settings(&r), camera: camera_description });
+}
@@ -0,0 +1,433 @@
+use std::f64::consts::PI;
+use crate::{vec3::{Vec3, dot, orthonormal_basis}, ray::Ray, hit::Hit, aabb::Aabb, hittable::Hittable, material::MaterialEnum};
+
+/// Fill in a hit on a shape
+/// Shapes know their surface exactly, so unlike meshes this is done for every hit found rather than only the closest
+/// # Arguments
+/// * 'r' - The ray that hit the shape
+/// * 't' - Distance along the ray to the hit
+/// * 'normal' - Unit normal pointing out of the shape
+/// * 'uv' - Texture coordinates at the hit
+/// * 'derivatives' - Change in position per unit of u and of v, together with the normal they form a right handed frame
+/// * 'material' - The shape's material
+fn shape_hit(r: Ray, t: f64, normal: Vec3, uv: (f64, f64), derivatives: (Vec3, Vec3), material: &MaterialEnum) -> Hit {
+    let mut hit = Hit::new();
+    hit.t = t;
+    hit.at = r.at(t);
+    hit.material = material.clone();
+    hit.uv = Vec3::new(uv.0, uv.1, 0.0);
+    hit.geometric_normal = normal;
+    hit.uv_derivatives = Some(derivatives);
+    hit.set_face_normal(r, normal, derivatives.0);
+    return hit;
+}
+
+/// Angle of a point around an axis as a fraction of a full turn
+/// # Arguments
+/// * 'x, y' - Position of the point in the plane perpendicular to the axis
+/// # Returns
+/// * 0 to 1, counterclockwise from x towards y
+fn turn(x: f64, y: f64) -> f64 {
+    let phi = y.atan2(x);
+    return if phi < 0.0 { phi / (2.0 * PI) + 1.0 } else { phi / (2.0 * PI) };
+}
+
+/// Bounding box of a disk
+/// # Arguments
+/// * 'center' - Center of the disk
+/// * 'normal' - Unit normal of the disk
+/// * 'radius' - Radius of the disk
+fn disk_bounds(center: Vec3, normal: Vec3, radius: f64) -> Aabb {
+
+    // Along each axis the disk reaches as far as its radius times how much it is turned away from that axis
+    let reach = |n: f64| radius * (1.0 - n * n).max(0.0).sqrt();
+    let extent = Vec3::new(reach(normal.x), reach(normal.y), reach(normal.z));
+    return Aabb::new(center - extent, center + extent);
+}
+
+/// Distance along a ray to a plane
+/// # Arguments
+/// * 'r' - The incoming ray
+/// * 'point' - Any point on the plane
+/// * 'normal' - Normal of the plane
+/// # Returns
+/// * t of the hit, None if the ray runs along the plane or the hit is outside the ray's interval
+fn plane_t(r: Ray, point: Vec3, normal: Vec3) -> Option<f64> {
+    let denom = dot(normal, r.direction);
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let t = dot(point - r.origin, normal) / denom;
+    if t > r.t_min && t < r.t_max {
+        return Some(t);
+    }
+    return None;
+}
+
+/// Sphere struct
+#[derive(Clone, Debug)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f64,
+    pub material: MaterialEnum
+}
+
+impl Sphere {
+    /// Create a new sphere
+    /// # Arguments
+    /// * 'center' - Center of the sphere
+    /// * 'radius' - Radius of the sphere
+    /// * 'material' - Material of the sphere
+    pub fn new(center: Vec3, radius: f64, material: MaterialEnum) -> Sphere {
+        return Sphere { center, radius, material };
+    }
+}
+
+impl Hittable for Sphere {
+
+    /// u goes around the sphere starting from -x, v from the bottom to the top
+    /// # Credit
+    /// * Intersection and texture coordinates from Peter Shirley's "Ray Tracing in One Weekend" series
+    /// * <https://raytracing.github.io/>
+    fn hit(&self, r: Ray) -> Hit {
+        let oc = r.origin - self.center;
+        let a = r.direction.length_squared();
+        let half_b = dot(oc, r.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return Hit::new();
+        }
+
+        // Take the nearer root, unless it is outside the ray's interval
+        let sqrtd = discriminant.sqrt();
+        let mut t = (-half_b - sqrtd) / a;
+        if t <= r.t_min || t >= r.t_max {
+            t = (-half_b + sqrtd) / a;
+            if t <= r.t_min || t >= r.t_max {
+                return Hit::new();
+            }
+        }
+
+        let n = (r.at(t) - self.center) / self.radius;
+        let theta = (-n.y).clamp(-1.0, 1.0).acos();
+        let phi = (-n.z).atan2(n.x) + PI;
+
+        // Moving around the sphere and from pole to pole, the poles themselves have no direction to go in
+        let sin_theta = (1.0 - n.y * n.y).max(0.0).sqrt().max(1e-9);
+        let dpdu = Vec3::new(n.z, 0.0, -n.x) * (2.0 * PI * self.radius);
+        let dpdv = Vec3::new(-n.x * n.y / sin_theta, sin_theta, -n.z * n.y / sin_theta) * (PI * self.radius);
+        return shape_hit(r, t, n, (phi / (2.0 * PI), theta / PI), (dpdu, dpdv), &self.material);
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        return Some(Aabb::new(self.center - extent, self.center + extent));
+    }
+}
+
+/// Infinite plane
+/// Has no bounds, so the world tests every ray against it rather than putting it in the BVH
+#[derive(Clone, Debug)]
+pub struct Plane {
+    /// Any point on the plane, texture coordinates are 0 there
+    pub point: Vec3,
+    /// Unit normal, the side the plane faces
+    pub normal: Vec3,
+    pub material: MaterialEnum
+}
+
+impl Plane {
+    /// Create a new plane
+    /// # Arguments
+    /// * 'point' - Any point on the plane
+    /// * 'normal' - Unit normal of the plane
+    /// * 'material' - Material of the plane
+    pub fn new(point: Vec3, normal: Vec3, material: MaterialEnum) -> Plane {
+        return Plane { point, normal, material };
+    }
+}
+
+impl Hittable for Plane {
+
+    /// Texture coordinates are distances along the plane from its point, so textures repeat every unit
+    fn hit(&self, r: Ray) -> Hit {
+        let Some(t) = plane_t(r, self.point, self.normal) else {
+            return Hit::new();
+        };
+        let (tangent, bitangent) = orthonormal_basis(self.normal);
+        let offset = r.at(t) - self.point;
+        return shape_hit(r, t, self.normal, (dot(offset, tangent), dot(offset, bitangent)), (tangent, bitangent), &self.material);
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return None;
+    }
+}
+
+/// Flat circular disk
+#[derive(Clone, Debug)]
+pub struct Disk {
+    pub center: Vec3,
+    /// Unit normal, the side the disk faces
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: MaterialEnum
+}
+
+impl Disk {
+    /// Create a new disk
+    /// # Arguments
+    /// * 'center' - Center of the disk
+    /// * 'normal' - Unit normal of the disk
+    /// * 'radius' - Radius of the disk
+    /// * 'material' - Material of the disk
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: MaterialEnum) -> Disk {
+        return Disk { center, normal, radius, material };
+    }
+}
+
+impl Hittable for Disk {
+
+    /// u goes around the disk, v from the rim in to the center
+    /// # Credit
+    /// * Texture coordinates follow pbrt's disk, <https://pbr-book.org/3ed-2018/Shapes/Other_Quadrics>
+    fn hit(&self, r: Ray) -> Hit {
+        let Some(t) = plane_t(r, self.center, self.normal) else {
+            return Hit::new();
+        };
+        let offset = r.at(t) - self.center;
+        let distance = offset.length();
+        if distance > self.radius {
+            return Hit::new();
+        }
+
+        let (tangent, bitangent) = orthonormal_basis(self.normal);
+        let (x, y) = (dot(offset, tangent), dot(offset, bitangent));
+        let dpdu = (bitangent * x - tangent * y) * (2.0 * PI);
+        let dpdv = if distance > 0.0 { offset * (-self.radius / distance) } else { -tangent * self.radius };
+        return shape_hit(r, t, self.normal, (turn(x, y), 1.0 - distance / self.radius), (dpdu, dpdv), &self.material);
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return Some(disk_bounds(self.center, self.normal, self.radius));
+    }
+}
+
+/// Box aligned with the x, y and z axes
+#[derive(Clone, Debug)]
+pub struct AaBox {
+    /// Smallest corner of the box
+    pub min: Vec3,
+    /// Largest corner of the box
+    pub max: Vec3,
+    pub material: MaterialEnum
+}
+
+impl AaBox {
+    /// Create a new box
+    /// # Arguments
+    /// * 'min, max' - Opposite corners of the box, in any order
+    /// * 'material' - Material of the box
+    pub fn new(min: Vec3, max: Vec3, material: MaterialEnum) -> AaBox {
+        return AaBox { min: min.min(max), max: min.max(max), material };
+    }
+}
+
+impl Hittable for AaBox {
+
+    /// Each face has its own texture coordinates, 0 to 1 across it
+    fn hit(&self, r: Ray) -> Hit {
+
+        // Where the ray enters and leaves each pair of faces, and which axis it entered and left by last
+        let (mut t_near, mut t_far) = (f64::NEG_INFINITY, f64::INFINITY);
+        let (mut near_axis, mut far_axis) = (0, 0);
+        for axis in 0..3 {
+
+            // Running parallel to a pair of faces, 0 * inf would give NaN for a ray starting on one of them
+            if r.direction[axis] == 0.0 {
+                if r.origin[axis] < self.min[axis] || r.origin[axis] > self.max[axis] {
+                    return Hit::new();
+                }
+                continue;
+            }
+            let inv = 1.0 / r.direction[axis];
+            let t0 = (self.min[axis] - r.origin[axis]) * inv;
+            let t1 = (self.max[axis] - r.origin[axis]) * inv;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = axis;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = axis;
+            }
+        }
+        if t_near > t_far {
+            return Hit::new();
+        }
+
+        // Rays starting inside the box hit it on the way out
+        let (t, axis, entering) = if t_near > r.t_min && t_near < r.t_max {
+            (t_near, near_axis, true)
+        } else if t_far > r.t_min && t_far < r.t_max {
+            (t_far, far_axis, false)
+        } else {
+            return Hit::new();
+        };
+
+        // The face's normal points against the ray when entering and along it when leaving
+        let unit = |i: usize| [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)][i];
+        let sign = if (r.direction[axis] < 0.0) == entering { 1.0 } else { -1.0 };
+        let normal = unit(axis) * sign;
+
+        // The other two axes run across the face, u is flipped on the negative faces to keep the frame right handed
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let p = r.at(t);
+        let size = self.max - self.min;
+        let u = (p[a] - self.min[a]) / size[a];
+        let v = (p[b] - self.min[b]) / size[b];
+        let (u, dpdu) = if sign > 0.0 { (u, unit(a) * size[a]) } else { (1.0 - u, unit(a) * -size[a]) };
+        return shape_hit(r, t, normal, (u, v), (dpdu, unit(b) * size[b]), &self.material);
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return Some(Aabb::new(self.min, self.max));
+    }
+}
+
+/// Cylinder closed off with a disk at each end
+#[derive(Clone, Debug)]
+pub struct Cylinder {
+    /// Center of the bottom end
+    pub base: Vec3,
+    /// Unit vector from the bottom end towards the top
+    pub axis: Vec3,
+    pub radius: f64,
+    pub height: f64,
+    pub material: MaterialEnum
+}
+
+impl Cylinder {
+    /// Create a new cylinder
+    /// # Arguments
+    /// * 'base' - Center of the bottom end
+    /// * 'axis' - Unit vector from the bottom end towards the top
+    /// * 'radius' - Radius of the cylinder
+    /// * 'height' - Distance between the ends
+    /// * 'material' - Material of the cylinder
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, material: MaterialEnum) -> Cylinder {
+        return Cylinder { base, axis, radius, height, material };
+    }
+}
+
+impl Hittable for Cylinder {
+
+    /// On the side u goes around the cylinder and v from the bottom to the top, the ends are mapped like disks
+    fn hit(&self, r: Ray) -> Hit {
+
+        // Work in the cylinder's own frame, with the axis along z and the base at the origin
+        let (tangent, bitangent) = orthonormal_basis(self.axis);
+        let local = |v: Vec3| Vec3::new(dot(v, tangent), dot(v, bitangent), dot(v, self.axis));
+        let o = local(r.origin - self.base);
+        let d = local(r.direction);
+        let within = |t: f64| t > r.t_min && t < r.t_max;
+
+        // The side, solving for where the ray is the radius away from the axis
+        let mut closest: Option<(f64, bool)> = None;
+        let a = d.x * d.x + d.y * d.y;
+        let half_b = o.x * d.x + o.y * d.y;
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if a > 1e-12 && discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                let z = o.z + d.z * t;
+                if within(t) && (0.0..=self.height).contains(&z) {
+                    closest = Some((t, true));
+                    break;
+                }
+            }
+        }
+
+        // Then both ends, which only count if they're closer
+        if d.z.abs() > 1e-12 {
+            for z in [0.0, self.height] {
+                let t = (z - o.z) / d.z;
+                let (x, y) = (o.x + d.x * t, o.y + d.y * t);
+                if within(t) && x * x + y * y <= self.radius * self.radius && closest.is_none_or(|(closest, _)| t < closest) {
+                    closest = Some((t, false));
+                }
+            }
+        }
+
+        let Some((t, side)) = closest else {
+            return Hit::new();
+        };
+        let p = Vec3::new(o.x + d.x * t, o.y + d.y * t, o.z + d.z * t);
+        let around = (bitangent * p.x - tangent * p.y) * (2.0 * PI);
+        if side {
+            let normal = (tangent * p.x + bitangent * p.y) / self.radius;
+
+            // u runs clockwise seen from the top so the frame stays right handed with v going up
+            return shape_hit(r, t, normal, (1.0 - turn(p.x, p.y), p.z / self.height), (-around, self.axis * self.height), &self.material);
+        }
+
+        // The ends are disks facing away from each other, the bottom's u runs the other way for the same reason
+        let top = p.z > self.height * 0.5;
+        let distance = (p.x * p.x + p.y * p.y).sqrt();
+        let radial = if distance > 0.0 { (tangent * p.x + bitangent * p.y) * (-self.radius / distance) } else { -tangent * self.radius };
+        let v = 1.0 - distance / self.radius;
+        if top {
+            return shape_hit(r, t, self.axis, (turn(p.x, p.y), v), (around, radial), &self.material);
+        }
+        return shape_hit(r, t, -self.axis, (1.0 - turn(p.x, p.y), v), (-around, radial), &self.material);
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bottom = disk_bounds(self.base, self.axis, self.radius);
+        let top = disk_bounds(self.base + self.axis * self.height, self.axis, self.radius);
+        return Some(bottom.union(top));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Diffuse, texture::Texture};
+
+    fn unit_box() -> AaBox {
+        let material = MaterialEnum::Diffuse(Diffuse::new(Texture::Solid(Vec3::new(0.5, 0.5, 0.5))));
+        return AaBox::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), material);
+    }
+
+    #[test]
+    fn box_ray_grazing_a_face_hits_the_edge() {
+        // Starts on the plane of the top face and runs along it, into the front face's top edge
+        let hit = unit_box().hit(Ray::new(Vec3::new(0.25, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.geometric_normal.z, -1.0);
+        assert!(!hit.uv.x.is_nan() && !hit.uv.y.is_nan());
+
+        // Same along the bottom face, going the other way with a negative zero
+        let hit = unit_box().hit(Ray::new(Vec3::new(-0.0, -1.0, 5.0), Vec3::new(-0.0, 0.0, -1.0)));
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.geometric_normal.z, 1.0);
+    }
+
+    #[test]
+    fn box_ray_parallel_outside_misses() {
+        let hit = unit_box().hit(Ray::new(Vec3::new(0.0, 1.5, -5.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(hit.t < 0.0);
+        let hit = unit_box().hit(Ray::new(Vec3::new(-1.0001, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(hit.t < 0.0);
+    }
+
+    #[test]
+    fn box_ray_from_inside_hits_on_the_way_out() {
+        let hit = unit_box().hit(Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(hit.t, 1.0);
+        assert!(!hit.front_face);
+    }
+}
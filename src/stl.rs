@@ -0,0 +1,217 @@
+use std::{collections::HashMap, fs};
+use crate::{vec3::{Vec3, unit_vector}, mesh::{Mesh, MeshBuilder, Vertex}};
+
+/// Size of a binary STL's header and triangle count
+const HEADER_SIZE: usize = 84;
+
+/// Size of each triangle in a binary STL, a normal, 3 corners and 2 bytes nobody agrees on
+const TRIANGLE_SIZE: usize = 50;
+
+/// Load an ASCII or binary STL mesh
+/// STL repeats each corner in every facet using it, corners at exactly the same position are joined back into one vertex
+/// # Arguments
+/// * 'path' - Path of an STL file
+/// * 'smooth' - Whether the mesh is smooth shaded, the facet normals are ignored and averaged at each vertex instead
+/// # Returns
+/// * A mesh of every facet with the default material, or a message pointing at what couldn't be read
+pub fn load_stl(path: &str, smooth: bool) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+    return parse_stl(&bytes, path, smooth);
+}
+
+/// Read an STL mesh from the contents of a file, see load_stl
+/// # Arguments
+/// * 'bytes' - The whole file
+/// * 'path' - Path of the file, used in error messages
+/// * 'smooth' - Whether the mesh is smooth shaded
+fn parse_stl(bytes: &[u8], path: &str, smooth: bool) -> Result<Mesh, String> {
+
+    // Binary files can start with "solid" too, their size is the reliable way to tell them apart
+    // and text files never have zero bytes, so one that's been cut short isn't mistaken for text
+    let binary = bytes.len() >= HEADER_SIZE && {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        bytes.len() == HEADER_SIZE + count * TRIANGLE_SIZE
+    };
+
+    let facets = if binary {
+        read_binary(bytes)
+    } else if bytes.starts_with(b"solid") && !bytes.contains(&0) {
+        read_ascii(&String::from_utf8_lossy(bytes), path)?
+    } else {
+        return Err(format!("{}: not an STL file, or a binary one that's been cut short", path));
+    };
+
+    // Smooth meshes average their normals at each vertex, otherwise facets written with a zero normal get one from their winding order
+    let mut mesh = MeshBuilder::new();
+    let mut ids: HashMap<[u64; 3], usize> = HashMap::new();
+    for (normal, points) in facets {
+        let normal = if smooth || normal.near_zero() { None } else { Some(unit_vector(normal)) };
+        let corners = points.map(|p| {
+            let next = ids.len();
+            let id = *ids.entry([p.x, p.y, p.z].map(f64::to_bits)).or_insert(next);
+            return Vertex { normal, ..Vertex::new((id, 0), p) };
+        });
+        mesh.add_triangle(corners, smooth, 0);
+    }
+    return Ok(mesh.build());
+}
+
+/// Read every facet of a binary STL
+/// # Arguments
+/// * 'bytes' - The whole file, already checked to have the right size
+/// # Returns
+/// * Each facet's normal and corners
+fn read_binary(bytes: &[u8]) -> Vec<(Vec3, [Vec3; 3])> {
+    return bytes[HEADER_SIZE..].chunks_exact(TRIANGLE_SIZE).map(|facet| {
+        let float = |at: usize| f32::from_le_bytes([facet[at], facet[at + 1], facet[at + 2], facet[at + 3]]) as f64;
+        let vector = |at: usize| Vec3::new(float(at), float(at + 4), float(at + 8));
+        return (vector(0), [vector(12), vector(24), vector(36)]);
+    }).collect();
+}
+
+/// Read every facet of an ASCII STL
+/// # Arguments
+/// * 'text' - The whole file
+/// * 'path' - Path of the file, used in error messages
+/// # Returns
+/// * Each facet's normal and corners, or a message pointing at the line that couldn't be read
+fn read_ascii(text: &str, path: &str) -> Result<Vec<(Vec3, [Vec3; 3])>, String> {
+    let mut facets = Vec::new();
+    let mut normal = Vec3::new(0.0, 0.0, 0.0);
+    let mut corners: Vec<Vec3> = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}:{}: {}", path, number + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        // Only facets and their vertices matter, "outer loop", "endloop" and the solid's name carry nothing
+        let vector = |words: &[&str]| -> Result<Vec3, String> {
+            let v: Vec<f64> = words.iter().map(|w| w.parse::<f64>().map_err(|_| format!("'{}' is not a number", w))).collect::<Result<_, _>>()?;
+            if v.len() != 3 {
+                return Err(format!("expected 3 numbers, found {}", v.len()));
+            }
+            return Ok(Vec3::new(v[0], v[1], v[2]));
+        };
+        match words.as_slice() {
+            ["facet", "normal", rest @ ..] => {
+                normal = vector(rest).map_err(error)?;
+                corners.clear();
+            },
+            ["vertex", rest @ ..] => corners.push(vector(rest).map_err(error)?),
+            ["endfacet"] => {
+                if corners.len() != 3 {
+                    return Err(error(format!("facet has {} vertices, expected 3", corners.len())));
+                }
+                facets.push((normal, [corners[0], corners[1], corners[2]]));
+            },
+            _ => {}
+        }
+    }
+    return Ok(facets);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two facets folded along a shared edge, like the ridge of a roof
+    const ROOF: &str = "solid roof
+facet normal 0 0 0
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 0 1 1
+  endloop
+endfacet
+facet normal 0 0 0
+  outer loop
+    vertex 0 1 1
+    vertex 1 0 0
+    vertex 1 1 1
+  endloop
+endfacet
+facet normal 0 0 0
+  outer loop
+    vertex 0 1 1
+    vertex 1 1 1
+    vertex 0 2 0
+  endloop
+endfacet
+endsolid roof
+";
+
+    fn parse(smooth: bool) -> Mesh {
+        return parse_stl(ROOF.as_bytes(), "roof.stl", smooth).unwrap();
+    }
+
+    /// The same roof as a binary file
+    /// # Arguments
+    /// * 'header' - Start of the 80 byte header, the rest is zeros
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; HEADER_SIZE];
+        bytes[..header.len()].copy_from_slice(header);
+        bytes[80..84].copy_from_slice(&3u32.to_le_bytes());
+        let corners = [[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 1.0]], [[0.0, 1.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 1.0]], [[0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [0.0, 2.0, 0.0]]];
+        for facet in corners {
+            bytes.extend([0u8; 12]);
+            for corner in facet {
+                for v in corner {
+                    bytes.extend((v as f32).to_le_bytes());
+                }
+            }
+            bytes.extend([0u8; 2]);
+        }
+        return bytes;
+    }
+
+    #[test]
+    fn smooth_stl_shares_vertices_and_averages_normals() {
+        let mesh = parse(true);
+        assert_eq!(mesh.triangles.len(), 3);
+        assert_eq!(mesh.positions.len(), 5);
+
+        // The ridge vertex sits between both slopes, so its normal points straight up
+        let ridge = mesh.positions.iter().position(|p| p.x == 1.0 && p.y == 1.0 && p.z == 1.0).unwrap();
+        let n = mesh.normals[ridge];
+        assert!(n.x.abs() < 1e-9 && (n.y.abs() < 1e-9) && (n.z - 1.0).abs() < 1e-9, "{:?}", n);
+    }
+
+    #[test]
+    fn flat_stl_keeps_facet_normals() {
+        let mesh = parse(false);
+        assert!(mesh.triangles.iter().all(|t| !t.smooth));
+        for trig in mesh.triangles.iter() {
+            let n = mesh.face_normal(trig);
+            for v in trig.vertices {
+                assert!((mesh.normals[v as usize] - n).length() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let ascii = parse(true);
+
+        // Binary files are allowed to start with "solid" too, only their size tells them apart
+        for header in [&b"binary roof"[..], &b"solid roof"[..]] {
+            let mesh = parse_stl(&binary(header), "roof.stl", true).unwrap();
+            assert_eq!(mesh.triangles.len(), 3);
+            assert_eq!(mesh.positions.len(), 5);
+            for (a, b) in mesh.positions.iter().zip(ascii.positions.iter()).chain(mesh.normals.iter().zip(ascii.normals.iter())) {
+                assert!((*a - *b).length() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_cut_short_is_an_error() {
+        let bytes = binary(b"binary roof");
+        let error = parse_stl(&bytes[..bytes.len() - 1], "roof.stl", false).unwrap_err();
+        assert_eq!(error, "roof.stl: not an STL file, or a binary one that's been cut short");
+
+        // Even if it starts like a text file
+        let bytes = binary(b"solid roof");
+        let error = parse_stl(&bytes[..bytes.len() - 1], "roof.stl", false).unwrap_err();
+        assert_eq!(error, "roof.stl: not an STL file, or a binary one that's been cut short");
+    }
+}
@@ -0,0 +1,355 @@
+use std::sync::Arc;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::Deserialize;
+use crate::{vec3::{Vec3, dot, cross, unit_vector}, hit::Hit};
+
+/// Where a material's color comes from
+#[derive(Clone, Debug)]
+pub enum Texture {
+    /// Same color everywhere
+    Solid(Vec3),
+    /// Image wrapped onto the mesh using its texture coordinates
+    Image(Arc<ImageTexture>),
+    /// Colors stored at the mesh's vertices, blended across each triangle
+    Vertex,
+    /// 3D checkerboard of two textures, cells are scale units wide
+    Checker { even: Box<Texture>, odd: Box<Texture>, scale: f64 },
+    /// Smooth Perlin noise blending between two colors, scale is how many features fit in a unit
+    Noise { low: Vec3, high: Vec3, scale: f64, noise: Arc<Perlin> },
+    /// Several octaves of noise added up, giving a rougher cloudy look
+    Turbulence { low: Vec3, high: Vec3, scale: f64, noise: Arc<Perlin> },
+    /// Stripes along z bent by turbulence
+    Marble { low: Vec3, high: Vec3, scale: f64, noise: Arc<Perlin> }
+}
+
+impl Texture {
+
+    /// Look up the color at a hit
+    /// # Arguments
+    /// * 'hit' - Information about what we hit, including its texture coordinates
+    pub fn value(&self, hit: &Hit) -> Vec3 {
+        match self {
+            Texture::Solid(color) => return *color,
+            Texture::Image(image) => return image.sample(hit.uv.x, hit.uv.y),
+            Texture::Vertex => return hit.color,
+            Texture::Checker { even, odd, scale } => {
+
+                // Cells are centered on multiples of the scale, so surfaces at whole numbers don't sit right on an edge
+                let p = hit.at / *scale;
+                let cell = (p.x + 0.5).floor() as i64 + (p.y + 0.5).floor() as i64 + (p.z + 0.5).floor() as i64;
+                return if cell.rem_euclid(2) == 0 { even.value(hit) } else { odd.value(hit) };
+            },
+            Texture::Noise { low, high, scale, noise } => {
+                let t = 0.5 * (1.0 + noise.noise(hit.at * *scale));
+                return *low * (1.0 - t) + *high * t;
+            },
+            Texture::Turbulence { low, high, scale, noise } => {
+                let t = noise.turbulence(hit.at * *scale, 7).min(1.0);
+                return *low * (1.0 - t) + *high * t;
+            },
+            Texture::Marble { low, high, scale, noise } => {
+                let p = hit.at * *scale;
+                let t = 0.5 * (1.0 + (p.z + 10.0 * noise.turbulence(p, 7)).sin());
+                return *low * (1.0 - t) + *high * t;
+            }
+        }
+    }
+}
+
+/// Allow a plain color to be used wherever a texture is
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Texture {
+        return Texture::Solid(color);
+    }
+}
+
+/// What happens to texture coordinates outside 0 to 1
+/// * 'Repeat' - Tile the image
+/// * 'Clamp' - Stretch the edge pixels outwards
+/// * 'Mirror' - Tile the image, flipping every other copy
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror
+}
+
+impl WrapMode {
+
+    /// Wrap a pixel coordinate into the image
+    /// # Arguments
+    /// * 'i' - Column or row, possibly outside the image
+    /// * 'n' - Width or height of the image
+    fn apply(&self, i: i64, n: i64) -> usize {
+        match self {
+            WrapMode::Repeat => return i.rem_euclid(n) as usize,
+            WrapMode::Clamp => return i.clamp(0, n - 1) as usize,
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                return if m >= n { (2 * n - 1 - m) as usize } else { m as usize };
+            }
+        }
+    }
+}
+
+/// Image loaded for use as a texture
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// Linear colors, row by row from the top
+    pixels: Vec<Vec3>,
+    wrap: WrapMode
+}
+
+impl ImageTexture {
+
+    /// Load a PNG or JPEG image of colors
+    /// # Arguments
+    /// * 'path' - Image file, its colors are taken to be sRGB
+    /// * 'wrap' - What happens to texture coordinates outside the image
+    /// # Returns
+    /// * The texture, or a message describing why the image couldn't be loaded
+    pub fn load(path: &str, wrap: WrapMode) -> Result<ImageTexture, String> {
+
+        // Images store gamma encoded colors, rendering needs them linear
+        return ImageTexture::load_with(path, wrap, srgb_to_linear);
+    }
+
+    /// Load a PNG or JPEG image of data rather than colors, such as a normal map
+    /// # Arguments
+    /// * 'path' - Image file, its values are used as they are, 0 to 255 becoming 0.0 to 1.0
+    /// * 'wrap' - What happens to texture coordinates outside the image
+    pub fn load_linear(path: &str, wrap: WrapMode) -> Result<ImageTexture, String> {
+        return ImageTexture::load_with(path, wrap, |c| c as f64 / 255.0);
+    }
+
+    /// Decode a PNG or JPEG image already in memory, such as one embedded in a glTF file
+    /// # Arguments
+    /// * 'bytes' - The encoded image
+    /// * 'name' - What to call the image in error messages
+    /// * 'wrap' - What happens to texture coordinates outside the image
+    /// * 'srgb' - Whether the image holds sRGB colors, like load, or data, like load_linear
+    pub fn from_memory(bytes: &[u8], name: &str, wrap: WrapMode, srgb: bool) -> Result<ImageTexture, String> {
+        let image = image::load_from_memory(bytes).map_err(|e| format!("failed to load texture {}: {}", name, e))?;
+        return ImageTexture::from_image(image, name, wrap, if srgb { srgb_to_linear } else { |c| c as f64 / 255.0 });
+    }
+
+    /// Load an image, converting each channel with the given function
+    fn load_with(path: &str, wrap: WrapMode, convert: fn(u8) -> f64) -> Result<ImageTexture, String> {
+        let image = image::open(path).map_err(|e| format!("failed to load texture {}: {}", path, e))?;
+        return ImageTexture::from_image(image, path, wrap, convert);
+    }
+
+    /// Convert a decoded image, converting each channel with the given function
+    fn from_image(image: image::DynamicImage, name: &str, wrap: WrapMode, convert: fn(u8) -> f64) -> Result<ImageTexture, String> {
+        let image = image.into_rgb8();
+        if image.width() == 0 || image.height() == 0 {
+            return Err(format!("failed to load texture {}: image is empty", name));
+        }
+        let pixels = image.pixels().map(|p| Vec3::new(convert(p[0]), convert(p[1]), convert(p[2]))).collect();
+        return Ok(ImageTexture { width: image.width() as usize, height: image.height() as usize, pixels, wrap });
+    }
+
+    /// Create a copy of the texture with every pixel changed
+    /// # Arguments
+    /// * 'f' - Function giving a pixel's new color from its old one
+    pub fn map_pixels(&self, f: impl Fn(Vec3) -> Vec3) -> ImageTexture {
+        return ImageTexture { width: self.width, height: self.height, pixels: self.pixels.iter().map(|p| f(*p)).collect(), wrap: self.wrap };
+    }
+
+    /// Color of a single pixel, wrapping coordinates outside the image
+    fn pixel(&self, x: i64, y: i64) -> Vec3 {
+        let x = self.wrap.apply(x, self.width as i64);
+        let y = self.wrap.apply(y, self.height as i64);
+        return self.pixels[y * self.width + x];
+    }
+
+    /// Look up a color with bilinear filtering
+    /// # Arguments
+    /// * 'u, v' - Texture coordinates, (0,0) is the bottom left of the image
+    pub fn sample(&self, u: f64, v: f64) -> Vec3 {
+
+        // Flip v, texture coordinates go up while rows go down
+        // Pixel centers sit at half coordinates, so shift to blend between the 4 nearest
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        if !x.is_finite() || !y.is_finite() {
+            return self.pixels[0];
+        }
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x0 + 1, y0) * fx;
+        let bottom = self.pixel(x0, y0 + 1) * (1.0 - fx) + self.pixel(x0 + 1, y0 + 1) * fx;
+        return top * (1.0 - fy) + bottom * fy;
+    }
+}
+
+/// Perturbs the shading normal to add detail without adding triangles
+#[derive(Clone, Debug)]
+pub enum NormalMap {
+    /// Tangent space normal map, red along the tangent, green along the bitangent and blue along the normal
+    /// Strength scales how far the normals lean
+    Tangent { image: Arc<ImageTexture>, strength: f64 },
+    /// Height map, the surface is bumped outwards where it's bright
+    /// Strength is how far a height of 1.0 raises the surface, in the mesh's units
+    Bump { height: Texture, strength: f64 }
+}
+
+impl NormalMap {
+
+    /// Calculate the perturbed normal at a hit
+    /// # Arguments
+    /// * 'hit' - Information about what we hit, its texture coordinates and tangent frame must already be set
+    /// * 'normal' - Shading normal pointing out of the triangle
+    /// # Returns
+    /// * The new shading normal, still pointing out of the triangle
+    pub fn apply(&self, hit: &Hit, normal: Vec3) -> Vec3 {
+        match self {
+            NormalMap::Tangent { image, strength } => {
+
+                // Colors 0 to 1 map to directions -1 to 1
+                let c = image.sample(hit.uv.x, hit.uv.y);
+                let m = Vec3::new((c.x * 2.0 - 1.0) * strength, (c.y * 2.0 - 1.0) * strength, c.z * 2.0 - 1.0);
+                let perturbed = hit.tangent * m.x + hit.bitangent * m.y + normal * m.z;
+                if perturbed.near_zero() {
+                    return normal;
+                }
+                return unit_vector(perturbed);
+            },
+            NormalMap::Bump { height, strength } => {
+
+                // How the surface moves along the texture coordinates, the tangent frame if there aren't any
+                // Flattened onto the shading normal's plane so smooth shading survives
+                let (dpdu, dpdv) = hit.uv_derivatives.unwrap_or((hit.tangent, hit.bitangent));
+                let dpdu = dpdu - normal * dot(normal, dpdu);
+                let dpdv = dpdv - normal * dot(normal, dpdv);
+
+                // Finite differences of the height, moving both the texture coordinates and the position
+                // so image and procedural heights both see the step
+                let delta = 0.0005;
+                let h = height.value(hit).luminance();
+                let mut shifted = hit.clone();
+                shifted.uv = hit.uv + Vec3::new(delta, 0.0, 0.0);
+                shifted.at = hit.at + dpdu * delta;
+                let dhdu = (height.value(&shifted).luminance() - h) / delta * strength;
+                shifted.uv = hit.uv + Vec3::new(0.0, delta, 0.0);
+                shifted.at = hit.at + dpdv * delta;
+                let dhdv = (height.value(&shifted).luminance() - h) / delta * strength;
+
+                // Normal of the displaced surface, flipped back out if the texture coordinates are mirrored
+                let perturbed = cross(dpdu + normal * dhdu, dpdv + normal * dhdv);
+                if perturbed.near_zero() {
+                    return normal;
+                }
+                let perturbed = unit_vector(perturbed);
+                return if dot(perturbed, normal) < 0.0 { -perturbed } else { perturbed };
+            }
+        }
+    }
+}
+
+/// Number of gradients and entries in each permutation, must be a power of 2
+const PERLIN_SIZE: usize = 256;
+
+/// Perlin gradient noise
+/// # Credit
+/// * Shirley, "Ray Tracing: The Next Week", <https://raytracing.github.io/books/RayTracingTheNextWeek.html#perlinnoise>
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    /// Random unit vectors at the lattice points
+    gradients: Vec<Vec3>,
+    /// Shuffles of 0..PERLIN_SIZE, one per axis, picking which gradient each lattice point gets
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+
+impl Perlin {
+
+    /// Create a new noise generator
+    /// # Arguments
+    /// * 'seed' - Seed of the noise pattern, separate from the render seed so textures look the same every run
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..PERLIN_SIZE)
+            .map(|_| unit_vector(Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0))))
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..PERLIN_SIZE).collect();
+            p.shuffle(&mut rng);
+            return p;
+        };
+        let perm_x = permutation();
+        let perm_y = permutation();
+        let perm_z = permutation();
+        return Perlin { gradients, perm_x, perm_y, perm_z };
+    }
+
+    /// Calculate the noise at a point
+    /// # Returns
+    /// * Smoothly varying value, roughly between -1.0 and 1.0
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        // Hermite smoothing hides the lattice
+        let (uu, vv, ww) = (u * u * (3.0 - 2.0 * u), v * v * (3.0 - 2.0 * v), w * w * (3.0 - 2.0 * w));
+
+        // Blend the gradients of the 8 surrounding lattice points
+        let mask = PERLIN_SIZE as i64 - 1;
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    accum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * dot(self.gradients[index], weight);
+                }
+            }
+        }
+        return accum;
+    }
+
+    /// Add up several octaves of noise, each twice as detailed and half as strong as the last
+    /// # Arguments
+    /// * 'p' - Point to calculate the turbulence at
+    /// * 'depth' - Number of octaves
+    /// # Returns
+    /// * Value of 0.0 or more, rarely over 1.0
+    pub fn turbulence(&self, p: Vec3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut point = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(point);
+            weight *= 0.5;
+            point = point * 2.0;
+        }
+        return accum.abs();
+    }
+}
+
+/// Convert an 8 bit sRGB channel to linear light
+/// # Credit
+/// * <https://en.wikipedia.org/wiki/SRGB#From_sRGB_to_CIE_XYZ>
+pub fn srgb_to_linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+    if c <= 0.04045 {
+        return c / 12.92;
+    }
+    return ((c + 0.055) / 1.055).powf(2.4);
+}
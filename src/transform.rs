@@ -0,0 +1,285 @@
+use crate::{vec3::{Vec3, cross, dot, unit_vector}, ray::Ray, aabb::Aabb};
+
+/// Rows of a 4x4 matrix, applied to column vectors
+pub type Matrix = [[f64; 4]; 4];
+
+/// Affine transform, a 4x4 matrix whose bottom row is always 0, 0, 0, 1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub matrix: Matrix
+}
+
+impl Transform {
+
+    /// Create a transform that leaves everything where it is
+    pub fn identity() -> Transform {
+        return Transform { matrix: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]] };
+    }
+
+    /// Create a transform from a matrix stored column by column, the way glTF stores them
+    /// # Arguments
+    /// * 'columns' - The 4 columns of the matrix
+    pub fn from_columns(columns: Matrix) -> Transform {
+        let mut matrix = [[0.0; 4]; 4];
+        for (row, values) in matrix.iter_mut().enumerate() {
+            for (col, value) in values.iter_mut().enumerate() {
+                *value = columns[col][row];
+            }
+        }
+        return Transform { matrix };
+    }
+
+    /// Create a translation
+    /// # Arguments
+    /// * 'd' - Vec3 which contains the x,y,z directions to translate
+    pub fn translate(d: Vec3) -> Transform {
+        let mut t = Transform::identity();
+        t.matrix[0][3] = d.x;
+        t.matrix[1][3] = d.y;
+        t.matrix[2][3] = d.z;
+        return t;
+    }
+
+    /// Create a scale, which can be different along each axis
+    /// # Arguments
+    /// * 's' - Amount to scale along x, y and z
+    pub fn scale(s: Vec3) -> Transform {
+        let mut t = Transform::identity();
+        t.matrix[0][0] = s.x;
+        t.matrix[1][1] = s.y;
+        t.matrix[2][2] = s.z;
+        return t;
+    }
+
+    /// Create a rotation from Euler angles, rotating on x first, then y, then z
+    /// # Arguments
+    /// * 'r' - Vec3 in degrees NOT radians
+    pub fn rotate(r: Vec3) -> Transform {
+        let x = Transform::axis_angle(Vec3::new(1.0, 0.0, 0.0), r.x);
+        let y = Transform::axis_angle(Vec3::new(0.0, 1.0, 0.0), r.y);
+        let z = Transform::axis_angle(Vec3::new(0.0, 0.0, 1.0), r.z);
+        return x.then(&y).then(&z);
+    }
+
+    /// Create a rotation around an axis, counterclockwise looking down the axis towards the origin
+    /// # Arguments
+    /// * 'axis' - Direction to rotate around, doesn't need to be a unit vector
+    /// * 'degrees' - Angle to rotate by in degrees
+    /// # Credit
+    /// * Rodrigues' rotation formula
+    /// * <https://en.wikipedia.org/wiki/Rodrigues%27_rotation_formula>
+    pub fn axis_angle(axis: Vec3, degrees: f64) -> Transform {
+        let a = unit_vector(axis);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        return Transform { matrix: [
+            [cos + a.x * a.x * k, a.x * a.y * k - a.z * sin, a.x * a.z * k + a.y * sin, 0.0],
+            [a.y * a.x * k + a.z * sin, cos + a.y * a.y * k, a.y * a.z * k - a.x * sin, 0.0],
+            [a.z * a.x * k - a.y * sin, a.z * a.y * k + a.x * sin, cos + a.z * a.z * k, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ] };
+    }
+
+    /// Create a rotation from a quaternion
+    /// # Arguments
+    /// * 'q' - Quaternion as [x, y, z, w], the way glTF writes them, normalized before use
+    /// # Credit
+    /// * <https://en.wikipedia.org/wiki/Quaternions_and_spatial_rotation#Quaternion-derived_rotation_matrix>
+    pub fn quaternion(q: [f64; 4]) -> Transform {
+        let length = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+        let [x, y, z, w] = q.map(|c| c / length);
+        return Transform { matrix: [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ] };
+    }
+
+    /// Compose two transforms
+    /// # Arguments
+    /// * 'next' - Transform applied after this one
+    /// # Returns
+    /// * A single transform doing both
+    pub fn then(&self, next: &Transform) -> Transform {
+        let mut matrix = [[0.0; 4]; 4];
+        for (row, values) in matrix.iter_mut().enumerate() {
+            for (col, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|k| next.matrix[row][k] * self.matrix[k][col]).sum();
+            }
+        }
+        return Transform { matrix };
+    }
+
+    /// Column of the upper 3x3, where the transform sends an axis
+    fn column(&self, i: usize) -> Vec3 {
+        return Vec3::new(self.matrix[0][i], self.matrix[1][i], self.matrix[2][i]);
+    }
+
+    /// Determinant of the upper 3x3, negative when the transform mirrors and 0 when it flattens
+    pub fn determinant(&self) -> f64 {
+        return dot(self.column(0), cross(self.column(1), self.column(2)));
+    }
+
+    /// Calculate the transform that undoes this one
+    /// # Returns
+    /// * The inverse, None if the transform flattens space and can't be undone
+    pub fn inverse(&self) -> Option<Transform> {
+        let determinant = self.determinant();
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        // The rows of the inverse of the upper 3x3 are the cross products of its columns over the determinant
+        let (a, b, c) = (self.column(0), self.column(1), self.column(2));
+        let rows = [cross(b, c) / determinant, cross(c, a) / determinant, cross(a, b) / determinant];
+
+        // Then the translation has to be undone after the rest
+        let t = self.column(3);
+        let mut matrix = [[0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        for (values, row) in matrix.iter_mut().zip(rows) {
+            *values = [row.x, row.y, row.z, -dot(row, t)];
+        }
+        return Some(Transform { matrix });
+    }
+
+    /// Transform a direction, ignoring translation
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.matrix;
+        return Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z
+        );
+    }
+
+    /// Transform a position
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        return self.vector(p) + self.column(3);
+    }
+
+    /// Transform a normal, which has to use the inverse transpose to stay perpendicular under non uniform scaling
+    /// The cofactor matrix is the inverse transpose scaled by the determinant, its columns are the cross products of the matrix's
+    /// so this still works for transforms that can't be inverted
+    /// # Returns
+    /// * The transformed normal as a unit vector
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let (a, b, c) = (self.column(0), self.column(1), self.column(2));
+        let sign = self.determinant().signum();
+        return unit_vector((cross(b, c) * n.x + cross(c, a) * n.y + cross(a, b) * n.z) * sign);
+    }
+
+    /// Transform a ray, keeping its interval
+    /// The direction isn't normalized, so t measures the same hit before and after
+    pub fn ray(&self, r: Ray) -> Ray {
+        return Ray::new_bounded(self.point(r.origin), self.vector(r.direction), r.t_min, r.t_max);
+    }
+
+    /// Transform a bounding box
+    /// # Returns
+    /// * The smallest box containing all 8 of the transformed box's corners, empty boxes stay empty
+    pub fn bounds(&self, b: Aabb) -> Aabb {
+        if b.min.x > b.max.x {
+            return b;
+        }
+        let mut corners = Vec::new();
+        for x in [b.min.x, b.max.x] {
+            for y in [b.min.y, b.max.y] {
+                for z in [b.min.z, b.max.z] {
+                    corners.push(self.point(Vec3::new(x, y, z)));
+                }
+            }
+        }
+        return Aabb::from_points(&corners);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check two transforms match to within rounding
+    fn assert_close(a: &Transform, b: &Transform) {
+        for row in 0..4 {
+            for col in 0..4 {
+                assert!((a.matrix[row][col] - b.matrix[row][col]).abs() < 1e-9, "{:?} != {:?}", a.matrix, b.matrix);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let t = Transform::scale(Vec3::new(2.0, -0.5, 3.0))
+            .then(&Transform::rotate(Vec3::new(30.0, -45.0, 110.0)))
+            .then(&Transform::translate(Vec3::new(1.0, -2.0, 5.0)));
+        let inverse = t.inverse().unwrap();
+        assert_close(&t.then(&inverse), &Transform::identity());
+        assert_close(&inverse.then(&t), &Transform::identity());
+    }
+
+    #[test]
+    fn flattened_transform_has_no_inverse() {
+        assert!(Transform::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn normal_stays_perpendicular_under_non_uniform_scale() {
+        let t = Transform::scale(Vec3::new(4.0, 1.0, 0.5)).then(&Transform::rotate(Vec3::new(0.0, 0.0, 20.0)));
+
+        // Two directions along a slanted surface and its normal
+        let along = [Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, -1.0)];
+        let n = unit_vector(cross(along[0], along[1]));
+        let moved = t.normal(n);
+        assert!((moved.length() - 1.0).abs() < 1e-9);
+        for v in along {
+            assert!(dot(moved, t.vector(v)).abs() < 1e-9);
+        }
+
+        // Transforming it like a direction would have tilted it off the surface
+        assert!(dot(unit_vector(t.vector(n)), t.vector(along[0])).abs() > 0.1);
+    }
+
+    #[test]
+    fn mirrored_normal_keeps_facing_out() {
+        let t = Transform::scale(Vec3::new(-1.0, 1.0, 1.0));
+        let n = t.normal(Vec3::new(1.0, 0.0, 0.0));
+        assert!((n.x + 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quaternion_matches_axis_angle() {
+        let axis = unit_vector(Vec3::new(1.0, 2.0, -3.0));
+        let degrees: f64 = 75.0;
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        let q = Transform::quaternion([axis.x * sin, axis.y * sin, axis.z * sin, cos]);
+        assert_close(&q, &Transform::axis_angle(axis, degrees));
+
+        // Quaternions don't have to be unit length
+        let (sin, cos) = (90f64.to_radians() / 2.0).sin_cos();
+        assert_close(&Transform::quaternion([0.0, 0.0, 3.0 * sin, 3.0 * cos]), &Transform::axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0));
+    }
+
+    #[test]
+    fn euler_angles_rotate_on_x_then_y_then_z() {
+        let t = Transform::rotate(Vec3::new(90.0, 90.0, 0.0));
+
+        // x takes y to z, then y takes z to x
+        let p = t.point(Vec3::new(0.0, 1.0, 0.0));
+        assert!((p - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn from_columns_reads_gltf_order() {
+        let t = Transform::from_columns([[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [4.0, 5.0, 6.0, 1.0]]);
+        assert_eq!(t, Transform::translate(Vec3::new(4.0, 5.0, 6.0)));
+    }
+
+    #[test]
+    fn bounds_contain_rotated_box() {
+        let b = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let moved = Transform::rotate(Vec3::new(0.0, 45.0, 0.0)).bounds(b);
+        let half_diagonal = 2f64.sqrt();
+        assert!((moved.max.x - half_diagonal).abs() < 1e-9);
+        assert!((moved.max.y - 1.0).abs() < 1e-9);
+    }
+}
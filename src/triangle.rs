@@ -1,45 +1,40 @@
-use crate::{vec3::{Vec3, cross, dot}, ray::Ray, hit::Hit};
+use crate::{vec3::{Vec3, cross, dot}, ray::Ray, hit::Hit, aabb::Aabb};
 
 /// Triangle struct
+/// Only indexes into its mesh's vertex arrays, so vertices shared between triangles are stored once
 #[derive(Copy, Clone, Debug)]
 pub struct Triangle {
-    /// The 3 points of a triangle
-    pub points: [Vec3; 3],
-    /// Flat shaded triangles single normal vector
-    pub normal: Vec3,
-    /// Whether or not the triangle is smoothly shaded
+    /// Indices of the 3 corners in the owning mesh's vertex arrays
+    pub vertices: [u32; 3],
+    /// Whether or not the triangle is smoothly shaded, otherwise its first corner's normal is used across all of it
     pub smooth: bool,
-    /// If smooth, contains 3 normals for the 3 points
-    pub normals: [Vec3; 3],
+    /// Index into the owning mesh's materials, 0 unless the mesh file assigns one
+    pub material: usize,
 }
 
 impl Triangle {
 
     /// Create a new triangle, default flat shaded
     /// # Arguments
-    /// * 'p1, p2, p3' - The three points of the triangle 
-    /// * 'n' - Triangle normal vector
+    /// * 'vertices' - Indices of the three corners in the mesh's vertex arrays
     /// # Returns
-    /// * Triangle with given points and normal, smooth is default off and empty normals per vertex
-    pub fn new(p1: Vec3, p2: Vec3, p3: Vec3, n: Vec3) -> Triangle {
-        return Triangle {
-            points: [p1, p2, p3],
-            normal: n,
-            smooth: false,
-            normals: [Vec3::new(0.0, 0.0, 0.0); 3],
-        };
+    /// * Triangle with the given corners, smooth is default off, the mesh's first material
+    pub fn new(vertices: [u32; 3]) -> Triangle {
+        return Triangle { vertices, smooth: false, material: 0 };
     }
 
-    /// Create an empty triangle
-    /// # Returns
-    /// * Triangle with (0,0,0) for all points and normals
-    pub fn new_empty() -> Triangle {
-        return Triangle::new(
-            Vec3::new(0.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, 0.0),
-        );
+    /// Look up the triangle's corners
+    /// # Arguments
+    /// * 'positions' - Positions of the owning mesh's vertices
+    pub fn points(&self, positions: &[Vec3]) -> [Vec3; 3] {
+        return self.vertices.map(|v| positions[v as usize]);
+    }
+
+    /// Calculate the bounding box of the triangle
+    /// # Arguments
+    /// * 'positions' - Positions of the owning mesh's vertices
+    pub fn bounds(&self, positions: &[Vec3]) -> Aabb {
+        return Aabb::from_points(&self.points(positions));
     }
 }
 
@@ -47,19 +42,21 @@ impl Triangle {
 
     /// Check if the triangle has been hit by the ray
     /// # Arguments
+    /// * 'positions' - Positions of the owning mesh's vertices
     /// * 'r' - The incoming ray
     /// # Returns
-    /// * Hit struct containing all the information of the triangle
+    /// * Hit struct with the distance and position of the hit, the mesh fills in the rest once it knows the closest
     /// # Credit
     /// * Using Möller–Trumbore intersection algorithm
     /// * The code was provided by Wikipedia in C++, translated by me
     /// * <https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm>
-    pub fn hit(&self, r: Ray) -> Hit {
+    pub fn hit(&self, positions: &[Vec3], r: Ray) -> Hit {
 
         // Create an empty hit object, this will get populated the ray hits the triangle
         let mut hit = Hit::new();
-        let edge1 = self.points[1] - self.points[0];
-        let edge2 = self.points[2] - self.points[0];
+        let points = self.points(positions);
+        let edge1 = points[1] - points[0];
+        let edge2 = points[2] - points[0];
         let h = cross(r.direction, edge2);
         let a = dot(edge1, h);
         const EPSILON: f64 = 0.0000001;
@@ -68,9 +65,9 @@ impl Triangle {
         }
 
         let f = 1.0 / a;
-        let s = r.origin - self.points[0];
+        let s = r.origin - points[0];
         let u = f * dot(s, h);
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return hit;
         }
 
@@ -82,18 +79,8 @@ impl Triangle {
 
         let t = f * dot(edge2, q);
 
-        // If this is true, this means the ray hit the triangle
-        if t > EPSILON {
-            let mut trig = Triangle::new_empty();
-            trig.points = self.points;
-            trig.normal = self.normal;
-
-            if self.smooth {
-                trig.smooth = true;
-                trig.normals = self.normals;
-            }
-
-            hit.triangle = trig;
+        // If this is true, this means the ray hit the triangle within the ray's interval
+        if t > r.t_min && t < r.t_max {
             hit.t = t;
             hit.at = r.at(t);
             return hit;
@@ -103,4 +90,4 @@ impl Triangle {
         }
 
     }
-}
\ No newline at end of file
+}
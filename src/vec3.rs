@@ -1,9 +1,11 @@
-use std::ops::{Div, Sub, Add, Mul};
-use rand::Rng;
-use crate::hit::Hit;
+use std::ops::{Div, Sub, Add, Mul, Neg, Index};
+use serde::Deserialize;
+use crate::random::random_range;
 
 /// Vec3 struct.
-#[derive(Copy, Clone, Debug)]
+/// Scene files write it as a [x, y, z] array
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(from = "[f64; 3]")]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -29,6 +31,21 @@ impl Vec3 {
         return (self.x*self.x) + (self.y*self.y) + (self.z*self.z);
     }
 
+    /// Component-wise minimum of two Vec3's
+    pub fn min(self, other: Vec3) -> Vec3 {
+        return Vec3::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z));
+    }
+
+    /// Component-wise maximum of two Vec3's
+    pub fn max(self, other: Vec3) -> Vec3 {
+        return Vec3::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z));
+    }
+
+    /// Perceived brightness of an r,g,b color
+    pub fn luminance(self) -> f64 {
+        return 0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z;
+    }
+
     /// Check if the parameters of a Vec3 are very close to 0
     pub fn near_zero(self) -> bool {
         let s = 1e-8;
@@ -36,6 +53,13 @@ impl Vec3 {
     }
 }
 
+/// Allow the Vec3 to be created from an [x, y, z] array
+impl From<[f64; 3]> for Vec3 {
+    fn from(a: [f64; 3]) -> Vec3 {
+        return Vec3::new(a[0], a[1], a[2]);
+    }
+}
+
 /// Allow the Vec3 to be divided by a double
 impl Div<f64> for Vec3 {
     type Output = Vec3;
@@ -71,6 +95,15 @@ impl Mul<f64> for Vec3 {
     }
 }
 
+/// Allow the Vec3 to be negated
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        return Vec3::new(-self.x, -self.y, -self.z);
+    }
+}
+
 /// Allow the Vec3 to be subtracted by another Vec3
 impl Add<Vec3> for Vec3 {
     type Output = Vec3;
@@ -117,13 +150,14 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
 
 /// Calculate the barycentric coordinates
 /// # Arguments
-/// * 'hit' - Hit struct which contains information of the triangle that was hit
+/// * 'points' - Corners of the triangle that was hit
+/// * 'p' - Point on the triangle
 /// # Returns
 /// * The u,w,v barycentric results in the Vec3's x,y,z positions
-pub fn barycentric(hit: Hit) -> Vec3 {
-    let v0 = hit.triangle.points[1] - hit.triangle.points[0];
-    let v1 = hit.triangle.points[2] - hit.triangle.points[0];
-    let v2 = hit.at - hit.triangle.points[0];
+pub fn barycentric(points: [Vec3; 3], p: Vec3) -> Vec3 {
+    let v0 = points[1] - points[0];
+    let v1 = points[2] - points[0];
+    let v2 = p - points[0];
 
     let d00 = dot(v0, v0);
     let d01 = dot(v0, v1);
@@ -156,8 +190,7 @@ impl Mul<Vec3> for Vec3 {
 /// # Returns
 /// * A random Vec3
 pub fn random_vec_range(min: f64, max: f64) -> Vec3 {
-    let mut rng = rand::thread_rng();
-    return Vec3::new(rng.gen_range(min..max), rng.gen_range(min..max), rng.gen_range(min..max));
+    return Vec3::new(random_range(min, max), random_range(min, max), random_range(min, max));
 }
 
 /// Calculate a random vector in a unit sphere
@@ -172,6 +205,17 @@ pub fn random_in_unit_sphere() -> Vec3 {
     }
 }
 
+/// Calculate a random vector in a unit disk on the xy plane
+pub fn random_in_unit_disk() -> Vec3 {
+    loop {
+        let p = Vec3::new(random_range(-1.0, 1.0), random_range(-1.0, 1.0), 0.0);
+        if p.length_squared() >= 1.0 {
+            continue;
+        }
+        return p;
+    }
+}
+
 /// Generate a random unit vector
 pub fn random_unit_vector() -> Vec3 {
     return unit_vector(random_in_unit_sphere());
@@ -185,4 +229,48 @@ pub fn random_unit_vector() -> Vec3 {
 /// * A new reflected vector
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     return v - n * 2.0 * dot(v,n);
-}
\ No newline at end of file
+}
+
+/// Refract a Vec3 through a surface using Snell's law
+/// # Arguments
+/// * 'uv' - The incoming unit vector
+/// * 'n' - The surface's unit normal, pointing against the incoming vector
+/// * 'etai_over_etat' - Index of refraction of the medium the vector leaves divided by the one it enters
+/// # Returns
+/// * The refracted vector
+pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
+    let cos_theta = dot(-uv, n).min(1.0);
+    let r_out_perp = (uv + n * cos_theta) * etai_over_etat;
+    let r_out_parallel = n * -(1.0 - r_out_perp.length_squared()).abs().sqrt();
+    return r_out_perp + r_out_parallel;
+}
+
+/// Allow the Vec3 to be indexed by axis, 0 is x, 1 is y and 2 is z
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range")
+        }
+    }
+}
+
+/// Build two unit vectors perpendicular to a unit vector and each other
+/// # Arguments
+/// * 'n' - Unit vector, normally a normal
+/// # Returns
+/// * Tangent and bitangent, together with n they form a right handed basis
+/// # Credit
+/// * Duff et al., "Building an Orthonormal Basis, Revisited", <https://jcgt.org/published/0006/01/01/>
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let tangent = Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let bitangent = Vec3::new(b, sign + n.y * n.y * a, -n.y);
+    return (tangent, bitangent);
+}
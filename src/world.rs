@@ -1,21 +1,71 @@
-use crate::{mesh::Mesh, ray::Ray, hit::Hit};
+use crate::{mesh::Mesh, instance::Instance, transform::Transform, hittable::{Hittable, HittableEnum}, ray::Ray, hit::Hit, aabb::Aabb, bvh::Bvh, vec3::{Vec3, dot, unit_vector},
+    light::{AreaLight, LightList, LightSample, PunctualLight}, material::{Material, MaterialEnum},
+    background::Background, random::random_double};
 
 /// World struct
 #[derive(Clone, Debug)]
 pub struct World {
-    /// All the meshes in the world
-    pub meshes: Vec<Mesh>
+    /// Every object with bounds, mesh instances and shapes
+    pub objects: Vec<HittableEnum>,
+    /// Acceleration structure over the bounds of every object, see build_bvh
+    bvh: Bvh,
+    /// Objects without bounds like planes, every ray is tested against all of them
+    pub unbounded: Vec<HittableEnum>,
+    /// Every emissive triangle, sampled directly when lighting a hit
+    pub lights: LightList,
+    /// Lights without any geometry, every one of them is checked at every hit
+    pub punctual_lights: Vec<PunctualLight>,
+    /// What rays see when they escape the world
+    pub background: Background
 }
 
 impl World {
     /// Create a new empty world
     pub fn new() -> World {
-        return World { meshes: Vec::new() };
+        return World { objects: Vec::new(), bvh: Bvh::new_empty(), unbounded: Vec::new(), lights: LightList::new(), punctual_lights: Vec::new(), background: Background::sky() };
     }
 
-    /// Add a mesh to the world
+    /// Add a mesh to the world where it is, with its own materials
     pub fn add(&mut self, mesh: Mesh) {
-        self.meshes.push(mesh);
+        let instance = Instance::new(mesh.into_shared(), Transform::identity()).expect("the identity can always be undone");
+        self.add_instance(instance);
+    }
+
+    /// Add an instance of a shared mesh to the world
+    pub fn add_instance(&mut self, instance: Instance) {
+
+        // Every emissive triangle becomes a light, once for each instance
+        for (i, trig) in instance.mesh.triangles.iter().enumerate() {
+            if let MaterialEnum::Emissive(emissive) = instance.material(i) {
+                let points = trig.points(&instance.mesh.positions).map(|p| instance.point(p));
+                let normal = instance.normal(instance.mesh.face_normal(trig));
+                self.lights.add(AreaLight::new(points, normal, emissive.color * emissive.strength));
+            }
+        }
+
+        self.add_object(HittableEnum::Instance(Box::new(instance)));
+    }
+
+    /// Add any object to the world
+    /// Emissive shapes light what they're seen from but aren't sampled as lights, only mesh triangles are
+    pub fn add_object(&mut self, object: HittableEnum) {
+        if object.bounds().is_none() {
+            self.unbounded.push(object);
+            return;
+        }
+        self.objects.push(object);
+    }
+
+    /// Build the BVH over every object with bounds
+    /// Must be called once everything has been added, objects added afterwards aren't hit until it's called again
+    pub fn build_bvh(&mut self) {
+        let bounds: Vec<Aabb> = self.objects.iter().filter_map(|o| o.bounds()).collect();
+        self.bvh = Bvh::new(&bounds);
+    }
+
+    /// Add a point, spot or directional light to the world
+    pub fn add_light(&mut self, light: PunctualLight) {
+        self.punctual_lights.push(light);
     }
 }
 
@@ -25,17 +75,84 @@ impl World {
     /// # Arguments
     /// * 'r' - The incoming ray
     /// # Returns
-    /// * A hit struct containing the closest hit triangle and its properties
+    /// * A hit struct containing the closest hit triangle along the ray and its properties
     pub fn hit(&self, r: Ray) -> Hit {
-        let mut closest_hit = Hit::new();
-        for mesh in self.meshes.iter() {
-            let hit = mesh.hit(r);
+        // Objects are skipped entirely if the ray misses their bounds or they're behind a closer hit
+        let mut closest_hit = self.bvh.hit(r, |i, r| self.objects[i].hit(r));
+
+        // Unbounded objects only need to be closer than what the BVH found
+        for object in self.unbounded.iter() {
+            let t_max = if closest_hit.t > 0.0 { closest_hit.t } else { r.t_max };
+            let hit = object.hit(Ray::new_bounded(r.origin, r.direction, r.t_min, t_max));
             if hit.t > 0.0 {
-                if hit.at.z > closest_hit.at.z {
-                    closest_hit = hit;
-                }
+                closest_hit = hit;
             }
         }
         return closest_hit;
     }
-}
\ No newline at end of file
+
+    /// Probability of sampling the environment map rather than an area light
+    /// Split evenly when there are both, otherwise all on whichever there is
+    fn environment_probability(&self) -> f64 {
+        match self.background.environment() {
+            Some(_) if self.lights.is_empty() => return 1.0,
+            Some(_) => return 0.5,
+            None => return 0.0
+        }
+    }
+
+    /// Pick a random point on a random light, or a direction from the environment map
+    /// # Arguments
+    /// * 'p' - Point the light is seen from
+    /// # Returns
+    /// * The light sample, None if there's nothing to sample
+    pub fn sample_light(&self, p: Vec3) -> Option<LightSample> {
+        let env_probability = self.environment_probability();
+        if let Some(map) = self.background.environment() {
+            if random_double() < env_probability {
+
+                // A sample landing on a pole has no direction, the other lights can still be tried
+                if let Some((direction, emission, pdf)) = map.sample() {
+                    return Some(LightSample { direction, distance: f64::INFINITY, emission, pdf: pdf * env_probability });
+                }
+            }
+        }
+
+        let mut sample = self.lights.sample(p)?;
+        sample.pdf *= 1.0 - env_probability;
+        return Some(sample);
+    }
+
+    /// Probability density that sample_light would have picked the direction a ray took to hit a light
+    /// # Arguments
+    /// * 'r' - Ray that hit the light
+    /// * 'hit' - Where the ray hit
+    /// # Returns
+    /// * Density per unit solid angle, 0.0 if the hit isn't on a light
+    pub fn light_pdf(&self, r: Ray, hit: &Hit) -> f64 {
+        let pdf_area = self.lights.pdf_area(hit.material.emitted(hit));
+        if !hit.light_sampled || pdf_area <= 0.0 {
+            return 0.0;
+        }
+
+        // Convert from per unit area on the light to per unit solid angle seen from the ray's origin
+        let distance = hit.t * r.direction.length();
+        let cosine = dot(unit_vector(r.direction), hit.geometric_normal).abs();
+        if cosine <= 1e-8 {
+            return 0.0;
+        }
+        return pdf_area * distance * distance / cosine * (1.0 - self.environment_probability());
+    }
+
+    /// Probability density that sample_light would have picked the direction of a ray that escaped the world
+    /// # Arguments
+    /// * 'r' - Ray that missed everything
+    /// # Returns
+    /// * Density per unit solid angle, 0.0 unless the background is an environment map
+    pub fn background_pdf(&self, r: Ray) -> f64 {
+        match self.background.environment() {
+            Some(map) => return map.pdf(unit_vector(r.direction)) * self.environment_probability(),
+            None => return 0.0
+        }
+    }
+}